@@ -0,0 +1,95 @@
+//! The command line handling common to the echo client and server.
+
+use std::fmt::Display;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use jdn_cli::CliError;
+
+use crate::endpoint::Endpoint;
+use crate::lifecycle::EchoService;
+
+/// The command that sets the address used by the service, either an IP address and port or `unix:` and a socket path.
+pub const SET_ADDRESS_COMMAND: &str = "set-address";
+/// The command that prints whether the service is running.
+pub const IS_RUNNING_COMMAND: &str = "is-running";
+/// The command that starts the service.
+pub const START_COMMAND: &str = "start";
+/// The command that stops the service.
+pub const STOP_COMMAND: &str = "stop";
+/// The command that prints the most recent error reported by the service.
+pub const LAST_ERROR_COMMAND: &str = "last-error";
+/// The command that loads the TLS certificate presented by the server, or pinned by the client.
+pub const SET_TLS_CERT_COMMAND: &str = "set-tls-cert";
+/// The command that loads the CA certificates used to verify a TLS peer.
+pub const SET_TLS_CA_COMMAND: &str = "set-tls-ca";
+/// The command that loads the TLS certificate and key presented by the client to a server that authenticates it.
+pub const SET_TLS_CLIENT_CERT_COMMAND: &str = "set-tls-client-cert";
+/// The commands handled by handle_common_command.
+pub const COMMON_COMMANDS: [&str; 5] = [
+    SET_ADDRESS_COMMAND,
+    IS_RUNNING_COMMAND,
+    START_COMMAND,
+    STOP_COMMAND,
+    LAST_ERROR_COMMAND,
+];
+
+/// Handles the given command if it is one of the common commands. Returns None if the command is not recognized.
+pub fn handle_common_command<S: EchoService>(
+    service: &Mutex<S>,
+    command: &str,
+    args: &[String],
+    writer: &mut dyn Write,
+) -> Option<Result<(), CliError>> {
+    let result = match command {
+        SET_ADDRESS_COMMAND => first_argument(args).and_then(|address| {
+            let address = Endpoint::from_str(address).map_err(CliError::ArgumentParseFailure)?;
+            service.lock().unwrap().set_address(address);
+            Ok(())
+        }),
+        IS_RUNNING_COMMAND => write_output(writer, service.lock().unwrap().is_running()),
+        START_COMMAND => service
+            .lock()
+            .unwrap()
+            .start()
+            .map_err(|e| CliError::ExecutionError(format!("Unable to start: {}", e))),
+        STOP_COMMAND => {
+            service.lock().unwrap().stop();
+            Ok(())
+        }
+        LAST_ERROR_COMMAND => match service.lock().unwrap().take_error() {
+            Some(e) => write_output(writer, e),
+            None => write_output(writer, "none"),
+        },
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Gets the first of the given arguments, or an error if there are none.
+pub fn first_argument(args: &[String]) -> Result<&String, CliError> {
+    args.first().ok_or(CliError::InvalidNumberOfArguments {
+        min: 1,
+        max: None,
+        given: 0,
+    })
+}
+
+/// Gets exactly two arguments, or an error if a different number was given.
+pub fn two_arguments(args: &[String]) -> Result<(&String, &String), CliError> {
+    match args {
+        [first, second] => Ok((first, second)),
+        _ => Err(CliError::InvalidNumberOfArguments {
+            min: 2,
+            max: Some(2),
+            given: args.len(),
+        }),
+    }
+}
+
+/// Writes the given value to the given writer on its own line.
+pub fn write_output(writer: &mut dyn Write, value: impl Display) -> Result<(), CliError> {
+    writeln!(writer, "{}", value)
+        .map_err(|_| CliError::ExecutionError(String::from("Unable to write output")))
+}
@@ -0,0 +1,81 @@
+//! The addresses on which an echo server can listen, and to which an echo client can connect.
+
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The prefix that marks a Unix domain socket path when an Endpoint is parsed from text, as in `unix:/tmp/echo.sock`.
+pub const UNIX_PREFIX: &str = "unix:";
+
+/// An address on which an echo server can listen, and to which an echo client can connect.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    /// An IP address and port, reached over TCP or UDP.
+    Tcp(SocketAddr),
+    /// The path of a Unix domain socket, reached as a stream.
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Gets the IP address and port of the endpoint, or None if it is a Unix domain socket.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Endpoint::Tcp(address) => Some(*address),
+            #[cfg(unix)]
+            Endpoint::Unix(_) => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for Endpoint {
+    fn from(address: SocketAddr) -> Self {
+        Endpoint::Tcp(address)
+    }
+}
+
+#[cfg(unix)]
+impl From<PathBuf> for Endpoint {
+    fn from(path: PathBuf) -> Self {
+        Endpoint::Unix(path)
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    /// Parses a Unix domain socket path prefixed with `unix:`, or otherwise an IP address and port.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            Some("") => Err(String::from("Unix domain socket path is empty")),
+            #[cfg(unix)]
+            Some(path) => Ok(Endpoint::Unix(PathBuf::from(path))),
+            #[cfg(not(unix))]
+            Some(_) => Err(String::from(
+                "Unix domain sockets are not supported on this platform",
+            )),
+            None => SocketAddr::from_str(s)
+                .map(Endpoint::Tcp)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "{}", address),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+/// Gets the placeholder address that identifies a peer with no IP address, such as one connected over a Unix domain
+/// socket or in memory. Placeholders use the unspecified IPv6 address, with the given ID as the port.
+pub fn unnamed_peer_addr(id: u16) -> SocketAddr {
+    SocketAddr::from((Ipv6Addr::UNSPECIFIED, id))
+}
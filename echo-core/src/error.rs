@@ -0,0 +1,87 @@
+//! The errors reported by the echo client and server.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+/// An error that occurred while running an echo client or server.
+#[derive(Debug)]
+pub enum EchoError {
+    /// The service was started while it was already running.
+    AlreadyRunning,
+    /// The operation requires the service to be running, but it is stopped.
+    NotRunning,
+    /// The operation did not complete before its timeout elapsed.
+    TimedOut,
+    /// A background thread could not be spawned.
+    ThreadSpawnFailed(io::Error),
+    /// The server could not bind to its address.
+    BindFailed(io::Error),
+    /// The client could not connect to its address.
+    ConnectFailed(io::Error),
+    /// Reading from or writing to a connection failed.
+    Io(io::Error),
+    /// A message was read as text, but was not valid UTF-8.
+    InvalidUtf8(FromUtf8Error),
+    /// A message could not be queued because the outbox is full.
+    OutboxFull,
+    /// A message was longer than the largest frame allowed, MAX_FRAME_SIZE in the framing module.
+    FrameTooLarge,
+    /// A TLS certificate, key or configuration could not be loaded, or was rejected.
+    TlsConfigFailed(String),
+    /// A client did not present a token accepted by the server. Holds the reason given by the server.
+    AuthenticationFailed(String),
+}
+
+impl fmt::Display for EchoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EchoError::AlreadyRunning => write!(f, "already running"),
+            EchoError::NotRunning => write!(f, "not running"),
+            EchoError::TimedOut => write!(f, "timed out"),
+            EchoError::ThreadSpawnFailed(e) => write!(f, "failed to spawn thread: {}", e),
+            EchoError::BindFailed(e) => write!(f, "failed to bind: {}", e),
+            EchoError::ConnectFailed(e) => write!(f, "failed to connect: {}", e),
+            EchoError::Io(e) => write!(f, "I/O error: {}", e),
+            EchoError::InvalidUtf8(e) => write!(f, "could not parse data: {}", e),
+            EchoError::OutboxFull => write!(f, "outbox full"),
+            EchoError::FrameTooLarge => write!(f, "frame too large"),
+            EchoError::TlsConfigFailed(e) => write!(f, "invalid TLS configuration: {}", e),
+            EchoError::AuthenticationFailed(reason) => {
+                write!(f, "authentication failed: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for EchoError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EchoError::AlreadyRunning
+            | EchoError::NotRunning
+            | EchoError::TimedOut
+            | EchoError::OutboxFull
+            | EchoError::FrameTooLarge
+            | EchoError::TlsConfigFailed(_)
+            | EchoError::AuthenticationFailed(_) => None,
+            EchoError::ThreadSpawnFailed(e)
+            | EchoError::BindFailed(e)
+            | EchoError::ConnectFailed(e)
+            | EchoError::Io(e) => Some(e),
+            EchoError::InvalidUtf8(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for EchoError {
+    fn from(e: io::Error) -> Self {
+        EchoError::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for EchoError {
+    fn from(e: FromUtf8Error) -> Self {
+        EchoError::InvalidUtf8(e)
+    }
+}
@@ -0,0 +1,143 @@
+//! Splitting of byte streams into discrete messages.
+
+use crate::error::EchoError;
+
+/// The size, in bytes, of the largest message that can be framed or decoded by a framing that delimits messages.
+/// It bounds the data a decoder holds while waiting for the end of a message.
+pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
+
+/// The method used to mark the boundaries of messages within a stream of bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// Each message is followed by a newline. Messages containing a newline will be received as multiple messages.
+    NewlineDelimited,
+    /// Each message is preceded by its length in bytes, as a 4-byte big-endian integer.
+    #[default]
+    LengthPrefixed,
+    /// Messages are not delimited. The bytes available from each read, or each datagram, form one message,
+    /// as in the standard Echo protocol described in RFC 862.
+    Raw,
+}
+
+impl Framing {
+    const LENGTH_PREFIX_SIZE: usize = 4;
+
+    /// Encodes the given message into a frame that can be written to a stream.
+    /// Returns FrameTooLarge if the framing delimits messages and the message is longer than MAX_FRAME_SIZE.
+    pub fn encode(&self, message: &[u8]) -> Result<Vec<u8>, EchoError> {
+        if *self != Framing::Raw && message.len() > MAX_FRAME_SIZE {
+            return Err(EchoError::FrameTooLarge);
+        }
+        let frame = match self {
+            Framing::NewlineDelimited => {
+                let mut frame = Vec::with_capacity(message.len() + 1);
+                frame.extend_from_slice(message);
+                frame.push(b'\n');
+                frame
+            }
+            Framing::LengthPrefixed => {
+                let mut frame = Vec::with_capacity(message.len() + Self::LENGTH_PREFIX_SIZE);
+                frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+                frame.extend_from_slice(message);
+                frame
+            }
+            Framing::Raw => message.to_vec(),
+        };
+        Ok(frame)
+    }
+
+    /// Constructs a new FrameDecoder for this framing.
+    pub fn decoder(&self) -> FrameDecoder {
+        FrameDecoder::new(*self)
+    }
+}
+
+/// Collects the bytes read from a stream and splits them into messages.
+#[derive(Debug)]
+pub struct FrameDecoder {
+    // The framing used to find the boundaries of messages.
+    framing: Framing,
+    // The bytes received that do not yet form a complete frame.
+    pending: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Constructs a new FrameDecoder with the given framing.
+    pub fn new(framing: Framing) -> Self {
+        FrameDecoder {
+            framing,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Appends the given bytes to the data received so far.
+    pub fn extend(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+    }
+
+    /// Removes and returns the next complete message, if one has been received.
+    /// Returns FrameTooLarge as soon as the message being received is known to be longer than MAX_FRAME_SIZE,
+    /// after which the stream cannot be decoded any further.
+    pub fn next_message(&mut self) -> Result<Option<Vec<u8>>, EchoError> {
+        match self.framing {
+            Framing::NewlineDelimited => {
+                let end = match self.pending.iter().position(|b| *b == b'\n') {
+                    Some(end) if end <= MAX_FRAME_SIZE => end,
+                    Some(_) => return Err(EchoError::FrameTooLarge),
+                    None if self.pending.len() > MAX_FRAME_SIZE => {
+                        return Err(EchoError::FrameTooLarge)
+                    }
+                    None => return Ok(None),
+                };
+                let mut message: Vec<u8> = self.pending.drain(..=end).collect();
+                message.pop();
+                Ok(Some(message))
+            }
+            Framing::LengthPrefixed => {
+                let prefix = match self.pending.get(..Framing::LENGTH_PREFIX_SIZE) {
+                    Some(prefix) => prefix,
+                    None => return Ok(None),
+                };
+                let len = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+                if len > MAX_FRAME_SIZE {
+                    return Err(EchoError::FrameTooLarge);
+                }
+                let end = Framing::LENGTH_PREFIX_SIZE + len;
+                if self.pending.len() < end {
+                    return Ok(None);
+                }
+                let message = self.pending[Framing::LENGTH_PREFIX_SIZE..end].to_vec();
+                self.pending.drain(..end);
+                Ok(Some(message))
+            }
+            Framing::Raw => {
+                if self.pending.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(std::mem::take(&mut self.pending)))
+            }
+        }
+    }
+
+    /// Removes and returns all of the bytes received that have not been returned as a message.
+    pub fn take_pending(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Appends the given bytes to the data received so far, and returns every message that is now complete.
+    /// Returns FrameTooLarge if a message is longer than MAX_FRAME_SIZE, as next_message does.
+    pub fn decode(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, EchoError> {
+        self.extend(data);
+        self.messages()
+    }
+
+    /// Removes and returns every complete message received so far.
+    /// Returns FrameTooLarge if a message is longer than MAX_FRAME_SIZE, as next_message does.
+    pub fn messages(&mut self) -> Result<Vec<Vec<u8>>, EchoError> {
+        let mut messages = Vec::new();
+        while let Some(message) = self.next_message()? {
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+}
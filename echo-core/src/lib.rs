@@ -0,0 +1,13 @@
+#![deny(missing_docs)]
+//! The protocol, framing, transports and lifecycle shared by the echo client and server
+
+pub mod cli;
+pub mod endpoint;
+pub mod error;
+pub mod framing;
+pub mod lifecycle;
+pub mod memory;
+pub mod protocol;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod transport;
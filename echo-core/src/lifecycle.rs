@@ -0,0 +1,93 @@
+//! The running state shared between an echo service and its background threads.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::endpoint::Endpoint;
+use crate::error::EchoError;
+
+/// A flag that indicates if a service is running, along with the last error reported by its background threads.
+/// Clones share the same state, so background threads can observe when the service is stopped.
+#[derive(Clone, Debug, Default)]
+pub struct Lifecycle {
+    // The flag that indicates if the service is running.
+    running: Arc<AtomicBool>,
+    // The Condvar notified when the service is stopped, used to wake sleeping background threads.
+    stopped: Arc<(Mutex<()>, Condvar)>,
+    // The most recent error reported by a background thread.
+    last_error: Arc<Mutex<Option<EchoError>>>,
+}
+
+impl Lifecycle {
+    /// Constructs a new Lifecycle in the stopped state.
+    pub fn new() -> Self {
+        Lifecycle::default()
+    }
+
+    /// Moves to the running state. Returns an error if the service was already running.
+    pub fn begin(&self) -> Result<(), EchoError> {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return Err(EchoError::AlreadyRunning);
+        }
+        Ok(())
+    }
+
+    /// Moves to the stopped state, waking any background threads sleeping on this Lifecycle.
+    pub fn end(&self) {
+        let (lock, condvar) = &*self.stopped;
+        let _guard = lock.lock().unwrap();
+        self.running.store(false, Ordering::Relaxed);
+        condvar.notify_all();
+    }
+
+    /// Gets the flag that indicates if the service is running.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+
+    /// Sleeps for the given duration, returning early if the service is stopped.
+    /// Returns the flag that indicates if the service is still running.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let (lock, condvar) = &*self.stopped;
+        let mut guard = lock.lock().unwrap();
+        while self.is_running() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            guard = condvar.wait_timeout(guard, deadline - now).unwrap().0;
+        }
+        self.is_running()
+    }
+
+    /// Records the given error as the most recent error, replacing any error that has not been taken.
+    pub fn report(&self, error: EchoError) {
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+
+    /// Takes the most recent error, if one has been reported since the last call.
+    pub fn take_error(&self) -> Option<EchoError> {
+        self.last_error.lock().unwrap().take()
+    }
+}
+
+/// The lifecycle operations common to the echo client and server.
+pub trait EchoService {
+    /// Sets the address used by the service. The change will have no effect until the next call to start.
+    fn set_address(&mut self, address: Endpoint);
+
+    /// Gets the flag that indicates if the service is running.
+    fn is_running(&self) -> bool;
+
+    /// Asynchronously starts the service. Returns an error if the service is already running,
+    /// or if the background process could not be started.
+    fn start(&mut self) -> Result<(), EchoError>;
+
+    /// Stops the service, waiting for its background threads to exit.
+    fn stop(&mut self);
+
+    /// Takes the most recent error reported by the background process, if one has been reported since the last call.
+    fn take_error(&self) -> Option<EchoError>;
+}
@@ -0,0 +1,221 @@
+//! An in-process transport whose streams are pipes in memory, so that clients and servers can be exercised
+//! without sockets.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::endpoint::{unnamed_peer_addr, Endpoint};
+use crate::transport::{self, Acceptor, Connector, Transport};
+
+/// Creates a new in-memory transport, returning the Connector through which clients reach the server
+/// and the Acceptor from which the server accepts them. Connecting fails once the acceptor is dropped.
+pub fn channel() -> (MemoryConnector, MemoryAcceptor) {
+    let (sender, receiver) = mpsc::channel();
+    (
+        MemoryConnector {
+            sender: Mutex::new(sender),
+        },
+        MemoryAcceptor {
+            receiver: Mutex::new(receiver),
+            next_peer: AtomicU16::new(1),
+        },
+    )
+}
+
+/// The client side of an in-memory transport, created by channel.
+pub struct MemoryConnector {
+    // The sender through which the server's end of each new stream is passed to the acceptor.
+    sender: Mutex<mpsc::Sender<MemoryStream>>,
+}
+
+impl Connector for MemoryConnector {
+    /// Opens a new stream to the acceptor immediately, ignoring the timeout.
+    fn connect(&self, _timeout: Duration) -> io::Result<Box<dyn Transport>> {
+        let (client, server) = MemoryStream::pair();
+        self.sender
+            .lock()
+            .unwrap()
+            .send(server)
+            .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused))?;
+        Ok(Box::new(client))
+    }
+}
+
+/// The server side of an in-memory transport, created by channel.
+/// Clients have no address of their own, so each is given a placeholder from unnamed_peer_addr.
+pub struct MemoryAcceptor {
+    // The receiver of the server's end of each new stream.
+    receiver: Mutex<mpsc::Receiver<MemoryStream>>,
+    // The ID of the placeholder address given to the next client.
+    next_peer: AtomicU16,
+}
+
+impl Acceptor for MemoryAcceptor {
+    fn accept(&self) -> io::Result<(Box<dyn Transport>, SocketAddr)> {
+        match self.receiver.lock().unwrap().try_recv() {
+            Ok(stream) => Ok((Box::new(stream), transport::next_peer_addr(&self.next_peer))),
+            Err(_) => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+        }
+    }
+
+    /// An in-memory transport has no endpoint, so this always returns None.
+    fn local_endpoint(&self) -> Option<Endpoint> {
+        None
+    }
+}
+
+/// One end of an in-memory duplex pipe. Data written to one end is read from the other, in order.
+/// Writes never block, since the pipe is unbounded. The pipe closes when either end is shut down,
+/// or when every handle to either end has been dropped.
+pub struct MemoryStream {
+    // The state of this end of the pipe, shared by every handle to it.
+    end: Arc<End>,
+}
+
+impl MemoryStream {
+    /// Creates a new pipe, returning its two ends.
+    pub fn pair() -> (MemoryStream, MemoryStream) {
+        let forward = Arc::new(Pipe::default());
+        let backward = Arc::new(Pipe::default());
+        let first = End {
+            incoming: Arc::clone(&backward),
+            outgoing: Arc::clone(&forward),
+            nonblocking: AtomicBool::new(false),
+        };
+        let second = End {
+            incoming: forward,
+            outgoing: backward,
+            nonblocking: AtomicBool::new(false),
+        };
+        (
+            MemoryStream {
+                end: Arc::new(first),
+            },
+            MemoryStream {
+                end: Arc::new(second),
+            },
+        )
+    }
+}
+
+impl Read for MemoryStream {
+    /// Reads the next available data, waiting for some to arrive unless the stream is non-blocking.
+    /// Returns 0 once the pipe is closed and all data written before it closed has been read.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pipe = &self.end.incoming;
+        let mut state = pipe.state.lock().unwrap();
+        loop {
+            if !state.data.is_empty() || buf.is_empty() {
+                let len = buf.len().min(state.data.len());
+                for (byte, value) in buf.iter_mut().zip(state.data.drain(..len)) {
+                    *byte = value;
+                }
+                return Ok(len);
+            }
+            if state.closed {
+                return Ok(0);
+            }
+            if self.end.nonblocking.load(Ordering::Relaxed) {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock));
+            }
+            state = pipe.readable.wait(state).unwrap();
+        }
+    }
+}
+
+impl Write for MemoryStream {
+    /// Writes all of the given data to the pipe. Returns BrokenPipe if the pipe is closed.
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let pipe = &self.end.outgoing;
+        let mut state = pipe.state.lock().unwrap();
+        if state.closed {
+            return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+        }
+        state.data.extend(data);
+        pipe.readable.notify_all();
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MemoryStream {
+    fn kind(&self) -> &'static str {
+        "MemoryStream"
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(MemoryStream {
+            end: Arc::clone(&self.end),
+        }))
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(unnamed_peer_addr(0))
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        self.end.nonblocking.store(nonblocking, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        self.end.close();
+        Ok(())
+    }
+}
+
+// One end of a pipe, along with the flag that indicates if its reads are non-blocking.
+struct End {
+    // The pipe from which this end reads.
+    incoming: Arc<Pipe>,
+    // The pipe to which this end writes.
+    outgoing: Arc<Pipe>,
+    // The flag that indicates if reads return WouldBlock rather than waiting for data.
+    nonblocking: AtomicBool,
+}
+
+impl End {
+    fn close(&self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+impl Drop for End {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+// The data travelling in one direction of a pipe.
+#[derive(Default)]
+struct Pipe {
+    // The data written but not yet read, and the flag that indicates if the pipe is closed.
+    state: Mutex<PipeState>,
+    // The Condvar notified whenever data is written or the pipe is closed.
+    readable: Condvar,
+}
+
+impl Pipe {
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.readable.notify_all();
+    }
+}
+
+#[derive(Default)]
+struct PipeState {
+    // The bytes written but not yet read, in order.
+    data: VecDeque<u8>,
+    // The flag that indicates if either end has closed the pipe.
+    closed: bool,
+}
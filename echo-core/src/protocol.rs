@@ -0,0 +1,138 @@
+//! The constants and message handling common to both ends of an echo connection.
+
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+use crate::error::EchoError;
+
+/// The interval, in milliseconds, at which background threads poll for work or retry failed operations.
+pub const DELAY_MS: u64 = 100;
+
+/// The size, in bytes, of the buffer used for each read from a stream.
+pub const BUFFER_SIZE: usize = 4096;
+
+/// The size, in bytes, of the largest datagram that can be sent or received over UDP.
+pub const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+/// The bytes that begin a message tagged with a correlation ID. They are followed by the ID, as 16 hexadecimal
+/// digits, and then the payload. Any message that happens to begin with these bytes is treated as tagged.
+pub const TAG_PREFIX: &[u8] = b"\x01JDN-ID:";
+
+// The number of hexadecimal digits used to write a correlation ID.
+const TAG_ID_LEN: usize = 16;
+
+/// Tags the given payload with a correlation ID, so that its echo can be matched to it.
+pub fn tag(id: u64, payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(TAG_PREFIX.len() + TAG_ID_LEN + payload.len());
+    message.extend_from_slice(TAG_PREFIX);
+    message.extend_from_slice(format!("{:016x}", id).as_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Splits a tagged message into its correlation ID and payload, or returns None if the message is not tagged.
+pub fn untag(message: &[u8]) -> Option<(u64, &[u8])> {
+    let rest = message.strip_prefix(TAG_PREFIX)?;
+    if rest.len() < TAG_ID_LEN {
+        return None;
+    }
+    let (id, payload) = rest.split_at(TAG_ID_LEN);
+    let id = std::str::from_utf8(id).ok()?;
+    if !id.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+        return None;
+    }
+    let id = u64::from_str_radix(id, 16).ok()?;
+    Some((id, payload))
+}
+
+/// The bytes that begin the first message a client sends to a server that requires a token. They are followed by
+/// the token, as UTF-8 text.
+pub const AUTH_PREFIX: &[u8] = b"\x01JDN-AUTH:";
+
+/// The bytes that begin the message a server sends to a client that failed to authenticate, just before closing
+/// the connection. They are followed by the reason, as UTF-8 text.
+pub const DENIED_PREFIX: &[u8] = b"\x01JDN-DENIED:";
+
+/// The message a server sends to a client whose token it accepted, before echoing anything to the client.
+/// The server's first message to a client that presented a token is therefore either this or a denial.
+pub const ACCEPTED: &[u8] = b"\x01JDN-ACCEPTED";
+
+/// Builds the message with which a client presents the given token to the server.
+pub fn auth_request(token: &str) -> Vec<u8> {
+    [AUTH_PREFIX, token.as_bytes()].concat()
+}
+
+/// Gets the token presented by an authentication message, or returns None if the message is not one.
+pub fn auth_token(message: &[u8]) -> Option<&str> {
+    std::str::from_utf8(message.strip_prefix(AUTH_PREFIX)?).ok()
+}
+
+/// Builds the message with which a server tells a client why it failed to authenticate.
+pub fn denial(reason: &str) -> Vec<u8> {
+    [DENIED_PREFIX, reason.as_bytes()].concat()
+}
+
+/// Gets the reason given by a denial message, or returns None if the message is not one.
+pub fn denial_reason(message: &[u8]) -> Option<Cow<'_, str>> {
+    message
+        .strip_prefix(DENIED_PREFIX)
+        .map(String::from_utf8_lossy)
+}
+
+/// A message received over an echo connection. The payload is kept as bytes, since it need not be valid UTF-8.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    // The bytes of the message.
+    payload: Vec<u8>,
+    // The address of the peer that sent the message.
+    source: SocketAddr,
+    // The time at which the message was received.
+    received_at: SystemTime,
+}
+
+impl Message {
+    /// Constructs a new Message with the given payload and source, received at the current time.
+    pub fn new(payload: Vec<u8>, source: SocketAddr) -> Self {
+        Message {
+            payload,
+            source,
+            received_at: SystemTime::now(),
+        }
+    }
+
+    /// Gets the bytes of the message.
+    pub fn bytes(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Gets the text of the message, or None if the message is not valid UTF-8.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.payload).ok()
+    }
+
+    /// Gets the text of the message, replacing any invalid UTF-8 sequences with U+FFFD.
+    pub fn text_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.payload)
+    }
+
+    /// Gets the address of the peer that sent the message.
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// Gets the time at which the message was received.
+    pub fn received_at(&self) -> SystemTime {
+        self.received_at
+    }
+
+    /// Consumes the message, returning its bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.payload
+    }
+
+    /// Consumes the message, returning its text. Returns an error if the message is not valid UTF-8.
+    pub fn into_text(self) -> Result<String, EchoError> {
+        String::from_utf8(self.payload).map_err(EchoError::from)
+    }
+}
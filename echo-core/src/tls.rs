@@ -0,0 +1,63 @@
+//! The loading of TLS certificates and keys shared by the echo client and server. Available with the `tls` feature.
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ConnectionCommon;
+
+use crate::error::EchoError;
+
+/// The certificate chain and private key presented to the other end of a connection.
+pub type Identity = (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>);
+
+/// Gets the cryptography used for every TLS connection. It is chosen explicitly, rather than relying on
+/// the process-wide default, so that enabling another rustls provider elsewhere does not change it.
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Loads every certificate in the given PEM file. Returns an error if the file cannot be read or holds no certificates.
+pub fn load_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, EchoError> {
+    let certificates = CertificateDer::pem_file_iter(path)
+        .and_then(|certificates| certificates.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| EchoError::TlsConfigFailed(format!("{}: {}", path.display(), e)))?;
+    if certificates.is_empty() {
+        return Err(EchoError::TlsConfigFailed(format!(
+            "{}: no certificates found",
+            path.display()
+        )));
+    }
+    Ok(certificates)
+}
+
+/// Copies the given certificate chain and private key.
+pub fn clone_identity((certificates, key): &Identity) -> Identity {
+    (certificates.clone(), key.clone_key())
+}
+
+/// Loads the first private key in the given PEM file.
+pub fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, EchoError> {
+    PrivateKeyDer::from_pem_file(path)
+        .map_err(|e| EchoError::TlsConfigFailed(format!("{}: {}", path.display(), e)))
+}
+
+/// Converts an error reported by a TLS connection into an I/O error, as reported by the connection's reads and writes.
+pub fn io_error(error: rustls::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+/// Writes every record the given TLS connection has produced to the given writer. A non-blocking writer may return
+/// WouldBlock, in which case the remaining records are kept for the next call.
+pub fn flush_tls<Data>(
+    session: &mut ConnectionCommon<Data>,
+    writer: &mut dyn Write,
+) -> io::Result<()> {
+    while session.wants_write() {
+        session.write_tls(writer)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,218 @@
+//! The streams over which echo clients and servers exchange data, and the means of establishing them.
+//! TCP and Unix domain sockets are provided, and other transports, such as the in-memory pipes of the memory module,
+//! can be plugged into a client with a Connector and into a server with an Acceptor.
+
+#[cfg(unix)]
+use std::fs;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+use crate::endpoint::{unnamed_peer_addr, Endpoint};
+
+/// A connected stream of bytes between a client and a server.
+pub trait Transport: Read + Write + Send {
+    /// Gets the name of the stream type, used to name the threads that serve it.
+    fn kind(&self) -> &'static str;
+
+    /// Creates a new handle to the same stream, so that it can be read and written from different threads.
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>>;
+
+    /// Gets the address of the peer, or a placeholder from unnamed_peer_addr if it has no IP address.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Sets whether reads return WouldBlock rather than waiting for data. This applies to every handle to the stream.
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+
+    /// Closes the stream in both directions, waking any read blocked on it.
+    fn shutdown(&self) -> io::Result<()>;
+}
+
+/// The means by which a client opens a stream to its server.
+pub trait Connector: Send + Sync {
+    /// Opens a new blocking stream to the server, waiting up to the given timeout for it to be accepted
+    /// if the transport supports it.
+    fn connect(&self, timeout: Duration) -> io::Result<Box<dyn Transport>>;
+}
+
+/// The means by which a server accepts streams from its clients.
+pub trait Acceptor: Send + Sync {
+    /// Accepts the next waiting client without blocking, along with the address that identifies it.
+    /// Returns WouldBlock if no client is waiting. A server stops on an error of kind InvalidInput or Unsupported,
+    /// which mean that no client can ever be accepted, and reports any other error before trying again.
+    fn accept(&self) -> io::Result<(Box<dyn Transport>, SocketAddr)>;
+
+    /// Gets the endpoint on which clients are accepted, or None if the transport has no endpoint.
+    fn local_endpoint(&self) -> Option<Endpoint>;
+}
+
+/// Binds a new acceptor to the given endpoint.
+/// See UnixAcceptor::bind for the handling of a socket file left at the path of a Unix domain socket.
+pub fn bind(endpoint: &Endpoint) -> io::Result<Box<dyn Acceptor>> {
+    match endpoint {
+        Endpoint::Tcp(address) => Ok(Box::new(TcpAcceptor::bind(*address)?)),
+        #[cfg(unix)]
+        Endpoint::Unix(path) => Ok(Box::new(UnixAcceptor::bind(path)?)),
+    }
+}
+
+impl Connector for Endpoint {
+    fn connect(&self, timeout: Duration) -> io::Result<Box<dyn Transport>> {
+        match self {
+            Endpoint::Tcp(address) => Ok(Box::new(TcpStream::connect_timeout(address, timeout)?)),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Ok(Box::new(UnixStream::connect(path)?)),
+        }
+    }
+}
+
+impl Transport for TcpStream {
+    fn kind(&self) -> &'static str {
+        "TcpStream"
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(TcpStream::try_clone(self)?))
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn kind(&self) -> &'static str {
+        "UnixStream"
+    }
+
+    fn try_clone(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(UnixStream::try_clone(self)?))
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(unnamed_peer_addr(0))
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+}
+
+/// A non-blocking acceptor of TCP clients.
+pub struct TcpAcceptor {
+    // The non-blocking listener from which clients are accepted.
+    listener: TcpListener,
+}
+
+impl TcpAcceptor {
+    /// Binds a new TcpAcceptor to the given address.
+    pub fn bind(address: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(TcpAcceptor { listener })
+    }
+}
+
+impl Acceptor for TcpAcceptor {
+    fn accept(&self) -> io::Result<(Box<dyn Transport>, SocketAddr)> {
+        let (socket, addr) = self.listener.accept()?;
+        Ok((Box::new(socket), addr))
+    }
+
+    fn local_endpoint(&self) -> Option<Endpoint> {
+        self.listener.local_addr().ok().map(Endpoint::Tcp)
+    }
+}
+
+/// A non-blocking acceptor of clients of a Unix domain socket. The socket file is removed when it is dropped.
+/// Clients have no address of their own, so each is given a placeholder from unnamed_peer_addr.
+#[cfg(unix)]
+pub struct UnixAcceptor {
+    // The non-blocking listener from which clients are accepted.
+    listener: UnixListener,
+    // The path of the socket file.
+    path: PathBuf,
+    // The ID of the placeholder address given to the next client.
+    next_peer: AtomicU16,
+}
+
+#[cfg(unix)]
+impl UnixAcceptor {
+    /// Binds a new UnixAcceptor to the given path. A socket file left at the path by a server that did not stop
+    /// cleanly is removed first, if nothing is listening on it. This is checked by connecting to the socket, so a
+    /// server that is listening on it sees a client connect and disconnect without sending anything.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        remove_stale_socket(path)?;
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(UnixAcceptor {
+            listener,
+            path: path.to_path_buf(),
+            next_peer: AtomicU16::new(1),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Acceptor for UnixAcceptor {
+    fn accept(&self) -> io::Result<(Box<dyn Transport>, SocketAddr)> {
+        let (socket, _) = self.listener.accept()?;
+        Ok((Box::new(socket), next_peer_addr(&self.next_peer)))
+    }
+
+    fn local_endpoint(&self) -> Option<Endpoint> {
+        Some(Endpoint::Unix(self.path.clone()))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixAcceptor {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Gets a placeholder address for the next client of an acceptor whose clients have no address of their own.
+/// ID 0 is skipped, since it identifies the server to a client. IDs are reused once 65,535 clients have connected,
+/// so a placeholder may be shared by two connected clients, and must not be relied on to tell them apart.
+pub(crate) fn next_peer_addr(next_peer: &AtomicU16) -> SocketAddr {
+    let mut id = next_peer.fetch_add(1, Ordering::Relaxed);
+    if id == 0 {
+        id = next_peer.fetch_add(1, Ordering::Relaxed);
+    }
+    unnamed_peer_addr(id)
+}
+
+// Removes the socket file at the given path if connecting to it is refused, which means nothing is listening on it.
+// Any other file is left in place, as is a socket that cannot be checked for another reason, so that binding fails.
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => match UnixStream::connect(path) {
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(path),
+            _ => Ok(()),
+        },
+        _ => Ok(()),
+    }
+}
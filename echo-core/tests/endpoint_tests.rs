@@ -0,0 +1,44 @@
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use jdn_echo_core::endpoint::{unnamed_peer_addr, Endpoint};
+
+#[test]
+fn test_endpoint_parse() {
+    let address = SocketAddr::from_str("127.0.0.1:8080").unwrap();
+    assert_eq!(
+        Endpoint::from_str("127.0.0.1:8080"),
+        Ok(Endpoint::Tcp(address))
+    );
+    assert_eq!(Endpoint::from(address).socket_addr(), Some(address));
+    assert!(Endpoint::from_str("localhost").is_err());
+    assert!(Endpoint::from_str("unix:").is_err());
+    #[cfg(unix)]
+    {
+        let endpoint = Endpoint::from_str("unix:/tmp/echo.sock").unwrap();
+        assert_eq!(endpoint, Endpoint::Unix(PathBuf::from("/tmp/echo.sock")));
+        assert_eq!(endpoint.socket_addr(), None);
+    }
+}
+
+#[test]
+fn test_endpoint_display_round_trip() {
+    let mut texts = vec!["127.0.0.1:8080", "[::1]:7"];
+    if cfg!(unix) {
+        texts.push("unix:/tmp/echo.sock");
+        texts.push("unix:relative/echo.sock");
+    }
+    for text in texts {
+        let endpoint = Endpoint::from_str(text).unwrap();
+        assert_eq!(endpoint.to_string(), text, "{} failed", text);
+    }
+}
+
+#[test]
+fn test_unnamed_peer_addr() {
+    assert_ne!(unnamed_peer_addr(1), unnamed_peer_addr(2));
+    assert_eq!(unnamed_peer_addr(3).port(), 3);
+    assert!(unnamed_peer_addr(3).ip().is_unspecified());
+}
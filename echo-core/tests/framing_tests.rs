@@ -0,0 +1,138 @@
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::framing::{FrameDecoder, Framing, MAX_FRAME_SIZE};
+
+const FRAMINGS: [Framing; 2] = [Framing::NewlineDelimited, Framing::LengthPrefixed];
+
+#[test]
+fn test_single_frame() {
+    for framing in FRAMINGS.iter() {
+        let mut decoder = framing.decoder();
+        let messages = decoder.decode(&framing.encode(b"hello").unwrap()).unwrap();
+        assert_eq!(messages, vec![b"hello".to_vec()], "{:?} failed", framing);
+        assert!(
+            decoder.next_message().unwrap().is_none(),
+            "{:?} failed",
+            framing
+        );
+    }
+}
+
+#[test]
+fn test_empty_frame() {
+    for framing in FRAMINGS.iter() {
+        let mut decoder = framing.decoder();
+        let messages = decoder.decode(&framing.encode(b"").unwrap()).unwrap();
+        assert_eq!(messages, vec![Vec::<u8>::new()], "{:?} failed", framing);
+    }
+}
+
+#[test]
+fn test_partial_reads() {
+    for framing in FRAMINGS.iter() {
+        let frame = framing.encode(b"split across reads").unwrap();
+        let mut decoder = FrameDecoder::new(*framing);
+        // Feed one byte at a time; only the final byte completes the message
+        for (i, byte) in frame.iter().enumerate() {
+            let messages = decoder.decode(&[*byte]).unwrap();
+            if i + 1 < frame.len() {
+                assert!(messages.is_empty(), "{:?} failed at byte {}", framing, i);
+            } else {
+                assert_eq!(
+                    messages,
+                    vec![b"split across reads".to_vec()],
+                    "{:?} failed",
+                    framing
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_multiple_frames_per_read() {
+    for framing in FRAMINGS.iter() {
+        let mut data = framing.encode(b"first").unwrap();
+        data.extend(framing.encode(b"second").unwrap());
+        data.extend(framing.encode(b"third").unwrap());
+        // Trailing partial frame is held until the rest arrives
+        let last = framing.encode(b"fourth").unwrap();
+        data.extend(&last[..3]);
+
+        let mut decoder = framing.decoder();
+        let messages = decoder.decode(&data).unwrap();
+        assert_eq!(
+            messages,
+            vec![b"first".to_vec(), b"second".to_vec(), b"third".to_vec()],
+            "{:?} failed",
+            framing
+        );
+        let messages = decoder.decode(&last[3..]).unwrap();
+        assert_eq!(messages, vec![b"fourth".to_vec()], "{:?} failed", framing);
+    }
+}
+
+#[test]
+fn test_length_prefixed_binary() {
+    let framing = Framing::LengthPrefixed;
+    let message = [0, b'\n', 255, 0, 1];
+    let frame = framing.encode(&message).unwrap();
+    assert_eq!(&frame[..4], &[0, 0, 0, 5]);
+    assert_eq!(
+        framing.decoder().decode(&frame).unwrap(),
+        vec![message.to_vec()]
+    );
+}
+
+#[test]
+fn test_raw_passthrough() {
+    let framing = Framing::Raw;
+    let message = [0, b'\n', 255, 0, 1];
+    assert_eq!(framing.encode(&message).unwrap(), message.to_vec());
+    let mut decoder = framing.decoder();
+    // Each read forms one message, however it was written
+    assert_eq!(decoder.decode(&message).unwrap(), vec![message.to_vec()]);
+    assert_eq!(decoder.decode(b"ab").unwrap(), vec![b"ab".to_vec()]);
+    assert!(decoder.decode(b"").unwrap().is_empty());
+}
+
+#[test]
+fn test_frame_too_large() {
+    let message = vec![b'a'; MAX_FRAME_SIZE + 1];
+    for framing in FRAMINGS.iter() {
+        assert!(
+            matches!(framing.encode(&message), Err(EchoError::FrameTooLarge)),
+            "{:?} encode failed - expected FrameTooLarge",
+            framing
+        );
+        assert!(
+            framing.encode(&message[1..]).is_ok(),
+            "{:?} encode failed at the limit",
+            framing
+        );
+    }
+    assert!(Framing::Raw.encode(&message).is_ok(), "Raw encode failed");
+
+    // A length prefix beyond the limit is rejected before the frame is buffered
+    let mut decoder = Framing::LengthPrefixed.decoder();
+    let prefix = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+    assert!(
+        matches!(decoder.decode(&prefix), Err(EchoError::FrameTooLarge)),
+        "Length prefix failed - expected FrameTooLarge"
+    );
+    let mut decoder = Framing::LengthPrefixed.decoder();
+    assert!(
+        matches!(
+            decoder.decode(&u32::MAX.to_be_bytes()),
+            Err(EchoError::FrameTooLarge)
+        ),
+        "Largest length prefix failed - expected FrameTooLarge"
+    );
+
+    // A newline-delimited frame is rejected once it grows beyond the limit without ending
+    let mut decoder = Framing::NewlineDelimited.decoder();
+    assert!(decoder.decode(&message[1..]).unwrap().is_empty());
+    assert!(
+        matches!(decoder.decode(b"a"), Err(EchoError::FrameTooLarge)),
+        "Unterminated frame failed - expected FrameTooLarge"
+    );
+}
@@ -0,0 +1,74 @@
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+use jdn_echo_core::endpoint::unnamed_peer_addr;
+use jdn_echo_core::memory::{self, MemoryStream};
+use jdn_echo_core::transport::{Acceptor, Connector, Transport};
+
+#[test]
+fn test_memory_stream() {
+    let (mut first, mut second) = MemoryStream::pair();
+    let mut buf = [0; 16];
+
+    // Data arrives in order, in both directions
+    first.write_all(b"hello").unwrap();
+    first.write_all(b" world").unwrap();
+    assert_eq!(second.read(&mut buf).unwrap(), 11);
+    assert_eq!(&buf[..11], b"hello world");
+    second.write_all(b"back").unwrap();
+    assert_eq!(first.read(&mut buf).unwrap(), 4);
+    assert_eq!(&buf[..4], b"back");
+
+    // A non-blocking read returns WouldBlock rather than waiting, for every handle
+    let mut clone = second.try_clone().unwrap();
+    second.set_nonblocking(true).unwrap();
+    assert_eq!(
+        clone.read(&mut buf).unwrap_err().kind(),
+        ErrorKind::WouldBlock
+    );
+
+    // Data written before a shutdown is still read, followed by the end of the stream
+    first.write_all(b"last").unwrap();
+    first.shutdown().unwrap();
+    assert_eq!(clone.read(&mut buf).unwrap(), 4);
+    assert_eq!(clone.read(&mut buf).unwrap(), 0);
+    assert_eq!(
+        clone.write(b"late").unwrap_err().kind(),
+        ErrorKind::BrokenPipe
+    );
+
+    // Dropping every handle to one end closes the pipe
+    let (first, mut second) = MemoryStream::pair();
+    std::mem::drop(first);
+    assert_eq!(second.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_memory_channel() {
+    let (connector, acceptor) = memory::channel();
+    assert_eq!(acceptor.local_endpoint(), None);
+    assert_eq!(
+        acceptor.accept().err().map(|e| e.kind()),
+        Some(ErrorKind::WouldBlock)
+    );
+
+    // Each client is accepted in turn, identified by a placeholder address
+    let mut clients = Vec::new();
+    for id in 1..=2 {
+        let mut client = connector.connect(Duration::ZERO).unwrap();
+        let (mut server, addr) = acceptor.accept().unwrap();
+        assert_eq!(addr, unnamed_peer_addr(id), "Client {} failed", id);
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping", "Client {} failed", id);
+        clients.push(client);
+    }
+
+    // Connecting fails once the acceptor is dropped
+    std::mem::drop(acceptor);
+    assert_eq!(
+        connector.connect(Duration::ZERO).err().map(|e| e.kind()),
+        Some(ErrorKind::ConnectionRefused)
+    );
+}
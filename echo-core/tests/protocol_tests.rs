@@ -0,0 +1,57 @@
+use jdn_echo_core::framing::Framing;
+use jdn_echo_core::protocol::{
+    auth_request, auth_token, denial, denial_reason, tag, untag, ACCEPTED, TAG_PREFIX,
+};
+
+#[test]
+fn test_tag_round_trip() {
+    for id in [0, 1, 0x0a0a_0a0a, u64::MAX].iter() {
+        let message = tag(*id, b"payload");
+        assert!(message.starts_with(TAG_PREFIX), "{} failed", id);
+        assert_eq!(
+            untag(&message),
+            Some((*id, &b"payload"[..])),
+            "{} failed",
+            id
+        );
+    }
+    assert_eq!(untag(&tag(7, b"")), Some((7, &b""[..])));
+}
+
+#[test]
+fn test_untagged_messages() {
+    assert_eq!(untag(b"hello"), None);
+    assert_eq!(untag(b""), None);
+    assert_eq!(untag(TAG_PREFIX), None);
+    let mut message = TAG_PREFIX.to_vec();
+    message.extend_from_slice(b"not a hex number");
+    assert_eq!(untag(&message), None);
+}
+
+#[test]
+fn test_tag_survives_framing() {
+    // IDs are written as text, so they never contain a newline that would split the frame
+    let framing = Framing::NewlineDelimited;
+    let message = tag(0x0a0a_0a0a_0a0a_0a0a, b"ping");
+    let messages = framing
+        .decoder()
+        .decode(&framing.encode(&message).unwrap())
+        .unwrap();
+    assert_eq!(messages, vec![message]);
+}
+
+#[test]
+fn test_authentication_messages() {
+    assert_eq!(auth_token(&auth_request("secret")), Some("secret"));
+    assert_eq!(auth_token(&auth_request("")), Some(""));
+    assert_eq!(auth_token(b"secret"), None);
+    assert_eq!(auth_token(&denial("secret")), None);
+    assert_eq!(
+        denial_reason(&denial("token required")).as_deref(),
+        Some("token required")
+    );
+    assert_eq!(denial_reason(&auth_request("secret")), None);
+    assert_eq!(denial_reason(b"hello"), None);
+    assert_eq!(denial_reason(ACCEPTED), None);
+    assert_eq!(auth_token(ACCEPTED), None);
+}
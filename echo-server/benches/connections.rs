@@ -0,0 +1,208 @@
+//! Compares the thread models of EchoServer by connecting many clients and echoing messages to each of them.
+//! A baseline replicating the thread-per-client design that EchoServer used before it had thread models is run first.
+//! The idle echo column is the round trip of one message sent after every client has been idle for a while, which
+//! shows how long a polling thread can leave new data waiting.
+//!
+//! Run with `cargo bench -p jdn-echo-server`. The number of clients and rounds can be set with the
+//! JDN_ECHO_BENCH_CLIENTS and JDN_ECHO_BENCH_ROUNDS environment variables. Each client uses three file
+//! descriptors, so large client counts may require raising the open file limit.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use jdn_echo_core::protocol::{BUFFER_SIZE, DELAY_MS};
+use jdn_echo_server::{EchoMode, EchoServer, EchoServerHandler, Framing, ThreadModel};
+
+const DEFAULT_CLIENTS: usize = 256;
+const DEFAULT_ROUNDS: usize = 20;
+// The time clients stay idle before the idle echo is measured, long enough for the polling delays to reach DELAY_MS.
+const IDLE_TIME: Duration = Duration::from_secs(1);
+
+// A handler that echoes every message without printing it.
+struct QuietHandler;
+
+impl EchoServerHandler for QuietHandler {}
+
+fn main() {
+    let clients = env_or("JDN_ECHO_BENCH_CLIENTS", DEFAULT_CLIENTS);
+    let rounds = env_or("JDN_ECHO_BENCH_ROUNDS", DEFAULT_ROUNDS);
+    println!(
+        "{} clients, {} rounds of one message per client",
+        clients, rounds
+    );
+    println!(
+        "{:<16} {:>8} {:>12} {:>12} {:>14} {:>14}",
+        "model", "threads", "connect ms", "echo ms", "messages/s", "idle echo ms"
+    );
+    let baseline = BaselineServer::start();
+    measure("Baseline", baseline.address, clients, rounds);
+    baseline.stop();
+    for thread_model in [
+        ThreadModel::PerConnection,
+        ThreadModel::Pool(1),
+        ThreadModel::Pool(4),
+    ] {
+        run(thread_model, clients, rounds);
+    }
+}
+
+fn run(thread_model: ThreadModel, client_count: usize, rounds: usize) {
+    let mut server = EchoServer::new(
+        SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        Arc::new(QuietHandler),
+    );
+    server.set_thread_model(thread_model);
+    server.set_mode(EchoMode::Sender);
+    server.start().unwrap();
+    let server_address = server.local_addr(Duration::from_secs(1)).unwrap();
+    measure(
+        &format!("{:?}", thread_model),
+        server_address,
+        client_count,
+        rounds,
+    );
+    server.stop();
+}
+
+fn measure(name: &str, server_address: SocketAddr, client_count: usize, rounds: usize) {
+    let connect_start = Instant::now();
+    let mut clients: Vec<TcpStream> = (0..client_count)
+        .map(|_| {
+            let client = TcpStream::connect(server_address).unwrap();
+            client
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            client
+        })
+        .collect();
+    // Every client is served once its first message is echoed.
+    echo_round(&mut clients);
+    let connect_elapsed = connect_start.elapsed();
+    let threads = thread_count();
+
+    let echo_start = Instant::now();
+    for _ in 0..rounds {
+        echo_round(&mut clients);
+    }
+    let echo_elapsed = echo_start.elapsed();
+    let throughput = (client_count * rounds) as f64 / echo_elapsed.as_secs_f64();
+
+    thread::sleep(IDLE_TIME);
+    let idle_start = Instant::now();
+    echo_round(&mut clients[..1]);
+    let idle_elapsed = idle_start.elapsed();
+
+    println!(
+        "{:<16} {:>8} {:>12} {:>12} {:>14.0} {:>14.1}",
+        name,
+        threads.map_or_else(|| String::from("n/a"), |threads| threads.to_string()),
+        connect_elapsed.as_millis(),
+        echo_elapsed.as_millis(),
+        throughput,
+        idle_elapsed.as_secs_f64() * 1000.0
+    );
+}
+
+// A server that echoes everything back to its sender, serving each client on its own thread that sleeps for DELAY_MS
+// whenever the client has sent nothing, as EchoServer did before it had thread models.
+struct BaselineServer {
+    // The address on which the server listens.
+    address: SocketAddr,
+    // The flag that indicates if the server is running.
+    running: Arc<AtomicBool>,
+    // The thread that accepts clients and joins their threads once the server stops.
+    accept_thread: JoinHandle<()>,
+}
+
+impl BaselineServer {
+    fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let address = listener.local_addr().unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let accept_running = Arc::clone(&running);
+        let accept_thread = thread::spawn(move || {
+            let mut client_threads = Vec::new();
+            while accept_running.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((socket, _)) => {
+                        let client_running = Arc::clone(&accept_running);
+                        client_threads.push(thread::spawn(move || {
+                            BaselineServer::serve(socket, &client_running)
+                        }));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(DELAY_MS));
+                    }
+                    Err(e) => panic!("{}", e),
+                }
+            }
+            for handle in client_threads {
+                let _ = handle.join();
+            }
+        });
+        BaselineServer {
+            address,
+            running,
+            accept_thread,
+        }
+    }
+
+    fn serve(mut socket: TcpStream, running: &AtomicBool) {
+        socket.set_nonblocking(true).unwrap();
+        let mut buf = [0; BUFFER_SIZE];
+        while running.load(Ordering::Relaxed) {
+            match socket.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => {
+                    if socket.write_all(&buf[..len]).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(DELAY_MS));
+                }
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn stop(self) {
+        self.running.store(false, Ordering::Relaxed);
+        let _ = self.accept_thread.join();
+    }
+}
+
+fn echo_round(clients: &mut [TcpStream]) {
+    let frame = Framing::default().encode(b"benchmark").unwrap();
+    for client in clients.iter_mut() {
+        client.write_all(&frame).unwrap();
+    }
+    let mut buf = vec![0; frame.len()];
+    for client in clients.iter_mut() {
+        client.read_exact(&mut buf).unwrap();
+    }
+}
+
+fn env_or(name: &str, default: usize) -> usize {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+// Reads the number of threads in this process, which includes the server's threads, where /proc is available.
+fn thread_count() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Threads:"))
+        .and_then(|count| count.trim().parse().ok())
+}
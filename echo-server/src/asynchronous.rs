@@ -0,0 +1,309 @@
+//! An asynchronous echo server, built on tokio. Available with the `tokio` feature.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, watch, Notify};
+use tokio::task::{JoinHandle, JoinSet};
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::framing::Framing;
+use jdn_echo_core::protocol::{BUFFER_SIZE, DELAY_MS};
+
+use crate::handler::{self, EchoServerHandler};
+use crate::mode::EchoMode;
+
+/// The outboxes through which echoed data is handed to the task serving each connected client, keyed by their address.
+type Clients = Arc<Mutex<HashMap<SocketAddr, Outbox>>>;
+
+/// The bounded queue of echoes waiting to be written to a client, along with the signal that disconnects the client
+/// once the queue overflows.
+struct Outbox {
+    // The Sender through which echoes are queued for the client.
+    sender: mpsc::Sender<Vec<u8>>,
+    // The signal that tells the client's task to disconnect it.
+    overflowed: Arc<Notify>,
+}
+
+/// A TCP server that echoes messages on a tokio runtime, serving each client on its own task.
+/// It shares its framing, echo modes and handler with EchoServer, but does not support RFC 862, thread models, TLS or tokens.
+pub struct AsyncEchoServer {
+    // The address on which the server listens.
+    address: SocketAddr,
+    // The address the listener is bound to, while running.
+    bound_address: Option<SocketAddr>,
+    // The framing used to delimit messages sent to and received from clients.
+    framing: Framing,
+    // The choice of which clients receive the echo of a message.
+    mode: Arc<Mutex<EchoMode>>,
+    // The number of echoes that can be queued for a client before it is disconnected.
+    outbox_capacity: usize,
+    // The handler notified of connections, messages and errors.
+    handler: Arc<dyn EchoServerHandler>,
+    // The Sender used to stop the accept task and every client task. Dropping it also stops them.
+    shutdown: Option<watch::Sender<bool>>,
+    // The task that accepts clients, awaited on shutdown.
+    accept_task: Option<JoinHandle<()>>,
+}
+
+impl AsyncEchoServer {
+    const DEFAULT_OUTBOX_CAPACITY: usize = 1024;
+
+    /// Constructs a new AsyncEchoServer with the given address and handler.
+    pub fn new(address: SocketAddr, handler: Arc<dyn EchoServerHandler>) -> Self {
+        AsyncEchoServer {
+            address,
+            bound_address: None,
+            framing: Framing::default(),
+            mode: Arc::new(Mutex::new(EchoMode::default())),
+            outbox_capacity: Self::DEFAULT_OUTBOX_CAPACITY,
+            handler,
+            shutdown: None,
+            accept_task: None,
+        }
+    }
+
+    /// Sets the framing used to delimit messages. The change will have no effect until the next call to start.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing
+    }
+
+    /// Sets the number of echoes that can be queued for a client that has not yet read the earlier ones, which is
+    /// 1024 by default. A client whose queue overflows is disconnected, and OutboxFull is reported for it.
+    /// The change will have no effect until the next call to start.
+    pub fn set_outbox_capacity(&mut self, capacity: usize) {
+        self.outbox_capacity = capacity.max(1)
+    }
+
+    /// Sets the choice of which clients receive the echo of a message. The change takes effect immediately.
+    pub fn set_mode(&self, mode: EchoMode) {
+        *self.mode.lock().unwrap() = mode;
+    }
+
+    /// Gets the choice of which clients receive the echo of a message.
+    pub fn mode(&self) -> EchoMode {
+        *self.mode.lock().unwrap()
+    }
+
+    /// Gets the address the server is listening on, or None if it is not running.
+    /// This reports the actual port when the server was configured with port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.bound_address
+    }
+
+    /// Gets the flag that indicates if the server is running.
+    pub fn is_running(&self) -> bool {
+        self.accept_task
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    /// Binds to the server's address, and starts accepting clients. Must be called from a tokio runtime.
+    /// Errors such as running out of file descriptors are reported, and accepting is retried after a delay that
+    /// doubles up to DELAY_MS in the protocol module. Accepting stops if the listener itself becomes unusable.
+    /// Returns an error if the server is already running, or if the address could not be bound.
+    pub async fn start(&mut self) -> Result<(), EchoError> {
+        if self.is_running() {
+            return Err(EchoError::AlreadyRunning);
+        }
+        let listener = TcpListener::bind(self.address)
+            .await
+            .map_err(EchoError::BindFailed)?;
+        self.bound_address = Some(listener.local_addr()?);
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        let context = AsyncContext {
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            framing: self.framing,
+            mode: Arc::clone(&self.mode),
+            outbox_capacity: self.outbox_capacity,
+            handler: Arc::clone(&self.handler),
+        };
+        self.accept_task = Some(tokio::spawn(accept_process(
+            listener,
+            context,
+            shutdown_receiver,
+        )));
+        self.shutdown = Some(shutdown_sender);
+        Ok(())
+    }
+
+    /// Stops accepting clients and closes every connection, waiting for all of the server's tasks to exit.
+    pub async fn shutdown(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(true);
+        }
+        if let Some(task) = self.accept_task.take() {
+            let _ = task.await;
+        }
+        self.bound_address = None;
+    }
+}
+
+/// The state shared between an AsyncEchoServer and its tasks.
+#[derive(Clone)]
+struct AsyncContext {
+    // The Senders used to hand echoed data to each connected client.
+    clients: Clients,
+    // The framing used to delimit messages sent to and received from clients.
+    framing: Framing,
+    // The choice of which clients receive the echo of a message.
+    mode: Arc<Mutex<EchoMode>>,
+    // The number of echoes that can be queued for a client before it is disconnected.
+    outbox_capacity: usize,
+    // The handler notified of connections, messages and errors.
+    handler: Arc<dyn EchoServerHandler>,
+}
+
+async fn accept_process(
+    listener: TcpListener,
+    context: AsyncContext,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut sessions = JoinSet::new();
+    let initial_delay = Duration::from_millis(1);
+    let mut delay = initial_delay;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((socket, addr)) => {
+                    delay = initial_delay;
+                    let (sender, receiver) = mpsc::channel(context.outbox_capacity);
+                    let overflowed = Arc::new(Notify::new());
+                    let outbox = Outbox {
+                        sender,
+                        overflowed: Arc::clone(&overflowed),
+                    };
+                    context.clients.lock().unwrap().insert(addr, outbox);
+                    context.handler.on_connect(addr);
+                    sessions.spawn(session_process(
+                        socket,
+                        addr,
+                        context.clone(),
+                        (receiver, overflowed),
+                        shutdown.clone(),
+                    ));
+                }
+                Err(e) => match e.kind() {
+                    io::ErrorKind::Interrupted => {}
+                    // The listener itself is unusable, so no client can ever be accepted again.
+                    io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported => {
+                        context.handler.on_error(None, &EchoError::Io(e));
+                        break;
+                    }
+                    // The error belongs to a connection that failed before it was accepted.
+                    io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset => {
+                        context.handler.on_error(None, &EchoError::Io(e));
+                    }
+                    // Errors such as running out of file descriptors may pass once clients disconnect.
+                    _ => {
+                        context.handler.on_error(None, &EchoError::Io(e));
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {}
+                            _ = shutdown.changed() => break,
+                        }
+                        delay = (delay * 2).min(Duration::from_millis(DELAY_MS));
+                    }
+                },
+            },
+            _ = shutdown.changed() => break,
+        }
+        // Collect the sessions of clients that have already disconnected.
+        while sessions.try_join_next().is_some() {}
+    }
+    while sessions.join_next().await.is_some() {}
+}
+
+async fn session_process(
+    socket: TcpStream,
+    addr: SocketAddr,
+    context: AsyncContext,
+    (mut outgoing, overflowed): (mpsc::Receiver<Vec<u8>>, Arc<Notify>),
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let (mut reader, mut writer) = socket.into_split();
+    let mut decoder = context.framing.decoder();
+    let mut buf = vec![0; BUFFER_SIZE];
+    // The rest of the echo being written, which never holds up reading, so that a client that stops reading
+    // is disconnected once its outbox overflows.
+    let mut pending = Vec::new();
+    loop {
+        tokio::select! {
+            result = reader.read(&mut buf) => match result {
+                Ok(0) => break,
+                Ok(len) => match decoder.decode(&buf[..len]) {
+                    Ok(messages) => {
+                        for message in messages {
+                            let reply = handler::handle_bytes(context.handler.as_ref(), addr, message);
+                            match reply.map(|data| context.framing.encode(&data)) {
+                                Some(Ok(frame)) => {
+                                    let mode = *context.mode.lock().unwrap();
+                                    echo(&context, mode, addr, frame);
+                                }
+                                Some(Err(e)) => context.handler.on_error(Some(addr), &e),
+                                None => {}
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        context.handler.on_error(Some(addr), &e);
+                        break;
+                    }
+                },
+                Err(e) => {
+                    context.handler.on_error(Some(addr), &EchoError::Io(e));
+                    break;
+                }
+            },
+            result = writer.write(&pending), if !pending.is_empty() => match result {
+                Ok(0) => {
+                    let e = io::Error::from(io::ErrorKind::WriteZero);
+                    context.handler.on_error(Some(addr), &EchoError::Io(e));
+                    break;
+                }
+                Ok(len) => {
+                    pending.drain(..len);
+                }
+                Err(e) => {
+                    context.handler.on_error(Some(addr), &EchoError::Io(e));
+                    break;
+                }
+            },
+            Some(data) = outgoing.recv(), if pending.is_empty() => pending = data,
+            _ = overflowed.notified() => break,
+            _ = shutdown.changed() => break,
+        }
+    }
+    context.clients.lock().unwrap().remove(&addr);
+    let _ = writer.shutdown().await;
+    context.handler.on_disconnect(addr);
+}
+
+fn echo(context: &AsyncContext, mode: EchoMode, sender: SocketAddr, data: Vec<u8>) {
+    let mut clients = context.clients.lock().unwrap();
+    let overflowed: Vec<SocketAddr> = clients
+        .iter()
+        .filter(|(addr, _)| mode.delivers_to(sender, **addr))
+        .filter_map(
+            |(addr, outbox)| match outbox.sender.try_send(data.clone()) {
+                Err(TrySendError::Full(_)) => Some(*addr),
+                // A closing task removes its outbox itself.
+                Ok(()) | Err(TrySendError::Closed(_)) => None,
+            },
+        )
+        .collect();
+    for addr in &overflowed {
+        if let Some(outbox) = clients.remove(addr) {
+            outbox.overflowed.notify_one();
+        }
+    }
+    drop(clients);
+    for addr in overflowed {
+        context.handler.on_error(Some(addr), &EchoError::OutboxFull);
+    }
+}
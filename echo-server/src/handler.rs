@@ -0,0 +1,76 @@
+//! Hooks that let embedders observe and transform the traffic handled by an EchoServer.
+
+use std::net::SocketAddr;
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::protocol;
+
+/// Receives the events of an EchoServer. Every method has a default implementation, so implementors only need
+/// to override the events they are interested in.
+pub trait EchoServerHandler: Send + Sync {
+    /// Called when a client connects to the server.
+    fn on_connect(&self, _peer: SocketAddr) {}
+
+    /// Called when a client that connected over TLS has presented a certificate the server verified,
+    /// with the certificate's subject, as soon as the handshake completes and before any of its messages are handled.
+    fn on_authenticate(&self, _peer: SocketAddr, _subject: &str) {}
+
+    /// Called for each message received from a client that is valid UTF-8, by the default implementation of on_bytes.
+    /// Returns the message to echo, or None to discard it.
+    fn on_message(&self, _peer: SocketAddr, message: String) -> Option<String> {
+        Some(message)
+    }
+
+    /// Called for each message received from a client. Returns the bytes to echo, or None to discard them.
+    /// By default, messages that are valid UTF-8 are passed to on_message, and any other message is echoed unchanged.
+    fn on_bytes(&self, peer: SocketAddr, message: Vec<u8>) -> Option<Vec<u8>> {
+        match String::from_utf8(message) {
+            Ok(text) => self.on_message(peer, text).map(String::into_bytes),
+            Err(e) => Some(e.into_bytes()),
+        }
+    }
+
+    /// Called when a client disconnects from the server.
+    fn on_disconnect(&self, _peer: SocketAddr) {}
+
+    /// Called when an error occurs. The peer is None if the error is not related to a specific client.
+    fn on_error(&self, _peer: Option<SocketAddr>, _error: &EchoError) {}
+}
+
+/// An EchoServerHandler that prints connections, messages and errors to stdout.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConsoleHandler;
+
+impl EchoServerHandler for ConsoleHandler {
+    fn on_connect(&self, peer: SocketAddr) {
+        println!("Accepted connection from {}", peer);
+    }
+
+    fn on_authenticate(&self, peer: SocketAddr, subject: &str) {
+        println!("Authenticated {} as {}", peer, subject);
+    }
+
+    fn on_bytes(&self, _peer: SocketAddr, message: Vec<u8>) -> Option<Vec<u8>> {
+        println!("{}", String::from_utf8_lossy(&message));
+        Some(message)
+    }
+
+    fn on_error(&self, _peer: Option<SocketAddr>, error: &EchoError) {
+        println!("{}", error);
+    }
+}
+
+/// Passes a message received from a client to the handler. A message tagged with a correlation ID is passed without
+/// its tag, which is added back to the reply so that the client can match the echo to its request.
+pub(crate) fn handle_bytes(
+    handler: &dyn EchoServerHandler,
+    peer: SocketAddr,
+    message: Vec<u8>,
+) -> Option<Vec<u8>> {
+    match protocol::untag(&message) {
+        Some((id, payload)) => handler
+            .on_bytes(peer, payload.to_vec())
+            .map(|reply| protocol::tag(id, &reply)),
+        None => handler.on_bytes(peer, message),
+    }
+}
@@ -1,118 +1,587 @@
 #![deny(missing_docs)]
 //! The simplest echo server
 
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod handler;
+pub mod mode;
+pub mod pool;
+mod session;
+mod stream;
+#[cfg(feature = "tls")]
+mod tls;
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::io;
-use std::io::Read;
-use std::net::SocketAddr;
-use std::net::TcpListener;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::thread;
-use std::time::Duration;
-
-/// A TCP server that echoes any message received from a client to all clients.
+use std::net::{SocketAddr, UdpSocket};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub use jdn_echo_core::endpoint::Endpoint;
+pub use jdn_echo_core::error::EchoError;
+pub use jdn_echo_core::framing::Framing;
+use jdn_echo_core::lifecycle::{EchoService, Lifecycle};
+use jdn_echo_core::protocol::{DELAY_MS, MAX_DATAGRAM_SIZE};
+use jdn_echo_core::transport::{self, Acceptor};
+
+#[cfg(feature = "tokio")]
+pub use crate::asynchronous::AsyncEchoServer;
+pub use crate::handler::{ConsoleHandler, EchoServerHandler};
+pub use crate::mode::EchoMode;
+use crate::pool::SessionRunner;
+pub use crate::pool::ThreadModel;
+use crate::session::{Clients, ServerContext, Session};
+#[cfg(feature = "tls")]
+use crate::tls::TlsSettings;
+
+/// A TCP or Unix domain socket server that echoes any message received from a client
+/// to the clients selected by its EchoMode.
 pub struct EchoServer {
-    // The address on which the server is listening.
-    address: SocketAddr,
-    // The flag that indicates if the server is currently running.
-    running: Arc<AtomicBool>,
+    // The endpoint on which the server should listen.
+    address: Endpoint,
+    // The acceptor from which the server accepts clients instead of binding to its address, if any.
+    acceptor: Option<Arc<dyn Acceptor>>,
+    // The acceptor from which the server is actually accepting clients, once binding succeeds.
+    bound_acceptor: Arc<BoundAcceptor>,
+    // The running state shared with the server's threads: whether it is running, the most recent error they reported,
+    // and the Condvar that wakes them when it stops.
+    lifecycle: Lifecycle,
+    // The outboxes of all currently connected clients, keyed by the ID of their connection.
+    clients: Clients,
+    // The number of echoes that can be queued for a client before it is disconnected.
+    outbox_capacity: usize,
+    // The delay before the first retry of a failed bind.
+    bind_retry_delay: Duration,
+    // The upper limit of the delay between retries of a failed bind.
+    bind_retry_max_delay: Duration,
+    // The framing used to delimit messages sent to and received from clients.
+    framing: Framing,
+    // The flag that indicates if the server should follow RFC 862, echoing raw bytes over TCP and UDP.
+    rfc862: bool,
+    // The choice of which clients receive the echo of a message, shared with the read threads.
+    mode: Arc<Mutex<EchoMode>>,
+    // The way threads are assigned to connected clients.
+    thread_model: ThreadModel,
+    // The handler notified of connections, messages and errors.
+    handler: Arc<dyn EchoServerHandler>,
+    // The choices that make up the TLS configuration used to serve clients.
+    #[cfg(feature = "tls")]
+    tls: TlsSettings,
+    // The tokens of which clients must present one before they are served, or None if clients are not authenticated.
+    tokens: Option<Arc<HashSet<String>>>,
+    // The largest message in which a client can present its token.
+    max_auth_size: usize,
+    // The time a client has to present its token once it connects.
+    auth_timeout: Duration,
+    // The handle of the thread that accepts client connections, joined when the server is stopped.
+    accept_thread: Option<JoinHandle<()>>,
 }
 
 impl EchoServer {
-    const DELAY_MS: u64 = 100;
+    const MAX_BIND_DELAY_MS: u64 = 1600;
+    const DEFAULT_OUTBOX_CAPACITY: usize = 1024;
+    const DEFAULT_MAX_AUTH_SIZE: usize = 1024;
+    const DEFAULT_AUTH_TIMEOUT_MS: u64 = 5000;
 
-    /// Constructs a new EchoServer with the given address.
-    pub fn new(address: SocketAddr) -> Self {
+    /// Constructs a new EchoServer with the given address, which notifies the given handler of its events.
+    pub fn new(address: impl Into<Endpoint>, handler: Arc<dyn EchoServerHandler>) -> Self {
         EchoServer {
-            address,
-            running: Arc::new(AtomicBool::new(false)),
+            address: address.into(),
+            acceptor: None,
+            bound_acceptor: Arc::new(BoundAcceptor::default()),
+            lifecycle: Lifecycle::new(),
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            outbox_capacity: Self::DEFAULT_OUTBOX_CAPACITY,
+            bind_retry_delay: Duration::from_millis(DELAY_MS),
+            bind_retry_max_delay: Duration::from_millis(Self::MAX_BIND_DELAY_MS),
+            framing: Framing::default(),
+            rfc862: false,
+            mode: Arc::new(Mutex::new(EchoMode::default())),
+            thread_model: ThreadModel::default(),
+            handler,
+            #[cfg(feature = "tls")]
+            tls: TlsSettings::default(),
+            tokens: None,
+            max_auth_size: Self::DEFAULT_MAX_AUTH_SIZE,
+            auth_timeout: Duration::from_millis(Self::DEFAULT_AUTH_TIMEOUT_MS),
+            accept_thread: None,
         }
     }
 
-    /// Sets the address on which the server is listening. The change will have no effect until the next call to start.
-    pub fn set_address(&mut self, address: SocketAddr) {
-        self.address = address
+    /// Sets the address or Unix domain socket path on which the server is listening. The change will have no effect
+    /// until the next call to start. If the port is 0, the server binds to a port assigned by the operating system,
+    /// which can be retrieved with local_addr. A stale socket file left at a Unix domain socket path is removed when
+    /// the server starts, and the socket file is removed again when the server stops.
+    pub fn set_address(&mut self, address: impl Into<Endpoint>) {
+        self.address = address.into()
+    }
+
+    /// Gets the address to which the server is bound, waiting up to the given timeout for binding to succeed.
+    /// Returns an error if the server is not running, if it is not bound before the timeout elapses,
+    /// or if it is bound to a Unix domain socket.
+    pub fn local_addr(&self, timeout: Duration) -> Result<SocketAddr, EchoError> {
+        self.local_endpoint(timeout)?.socket_addr().ok_or_else(|| {
+            EchoError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "server is bound to a Unix domain socket",
+            ))
+        })
+    }
+
+    /// Gets the endpoint to which the server is bound, waiting up to the given timeout for binding to succeed.
+    /// Returns an error if the server is not running, if it is not bound before the timeout elapses,
+    /// or if it is accepting clients from an acceptor that has no endpoint.
+    pub fn local_endpoint(&self, timeout: Duration) -> Result<Endpoint, EchoError> {
+        self.bound_acceptor
+            .wait(&self.lifecycle, timeout)?
+            .local_endpoint()
+            .ok_or_else(|| {
+                EchoError::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "server is not bound to an endpoint",
+                ))
+            })
+    }
+
+    /// Sets the acceptor from which the server accepts clients instead of binding to its address, such as the
+    /// MemoryAcceptor of an in-memory transport, or None to bind to the address. Clients of an acceptor are served
+    /// over TLS once set_tls_certificate has been called, and UDP datagrams are only echoed if it has a TCP endpoint.
+    /// The change will have no effect until the next call to start.
+    pub fn set_acceptor(&mut self, acceptor: Option<Arc<dyn Acceptor>>) {
+        self.acceptor = acceptor
+    }
+
+    /// Sets the delays between attempts to bind if binding fails. The delay starts at the given initial value
+    /// and doubles after each failure, up to the given maximum. The same delays are used between attempts to accept
+    /// clients after an error such as running out of file descriptors, which is reported without stopping the server.
+    /// The change will have no effect until the next call to start.
+    pub fn set_bind_backoff(&mut self, initial: Duration, max: Duration) {
+        self.bind_retry_delay = initial;
+        self.bind_retry_max_delay = max.max(initial);
+    }
+
+    /// Sets the framing used to delimit messages. The change will have no effect until the next call to start.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing
+    }
+
+    /// Sets the number of echoes that can be queued for a client that has not yet read the earlier ones, which is
+    /// 1024 by default. A client whose queue overflows is disconnected, and OutboxFull is reported for it.
+    /// The change will have no effect until the next call to start.
+    pub fn set_outbox_capacity(&mut self, capacity: usize) {
+        self.outbox_capacity = capacity.max(1)
+    }
+
+    /// Sets the way threads are assigned to connected clients, which is ThreadModel::PerConnection by default.
+    /// The change will have no effect until the next call to start.
+    pub fn set_thread_model(&mut self, thread_model: ThreadModel) {
+        self.thread_model = thread_model
+    }
+
+    /// Sets the flag that indicates if the server should offer the standard Echo service described in RFC 862.
+    /// When set, bytes received over TCP are echoed back to their sender verbatim, ignoring the framing, the
+    /// EchoMode and the handler's on_bytes, and the server also echoes each UDP datagram received on the same
+    /// address back to its source. The change will have no effect until the next call to start.
+    pub fn set_rfc862(&mut self, rfc862: bool) {
+        self.rfc862 = rfc862
+    }
+
+    /// Loads the certificate chain and private key the server presents to clients from the given PEM files,
+    /// and serves clients over TLS from the next call to start, whether they connect over TCP, a Unix domain socket
+    /// or an acceptor. UDP datagrams echoed under RFC 862 are not encrypted.
+    /// Returns an error if the files cannot be loaded, or if the key does not match the certificate.
+    #[cfg(feature = "tls")]
+    pub fn set_tls_certificate(
+        &mut self,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> Result<(), EchoError> {
+        self.tls.set_certificate(cert_path, key_path)
+    }
+
+    /// Stops serving clients over TLS, so they are served in plaintext from the next call to start.
+    /// Any CA certificates set by set_tls_client_ca are kept, and apply again once set_tls_certificate is called.
+    #[cfg(feature = "tls")]
+    pub fn clear_tls_certificate(&mut self) {
+        self.tls.clear_certificate()
+    }
+
+    /// Requires every client connecting over TLS to present a certificate that chains to one of the CA certificates
+    /// in the given PEM file. Clients without such a certificate are disconnected during the handshake, before any
+    /// of their messages are handled, and the subject of each accepted certificate is passed to the handler's
+    /// on_authenticate. Takes effect from the next call to start, once set_tls_certificate has been called.
+    /// Returns an error if the file cannot be loaded.
+    #[cfg(feature = "tls")]
+    pub fn set_tls_client_ca(&mut self, ca_path: &Path) -> Result<(), EchoError> {
+        self.tls.set_client_ca(ca_path)
+    }
+
+    /// Stops requiring clients connecting over TLS to present a certificate.
+    /// The change will have no effect until the next call to start.
+    /// Returns an error if the server's certificate can no longer be used without client authentication.
+    #[cfg(feature = "tls")]
+    pub fn clear_tls_client_ca(&mut self) -> Result<(), EchoError> {
+        self.tls.clear_client_ca()
+    }
+
+    /// Requires every client to present one of the given tokens in its first message, before any of its messages are
+    /// handled or it receives any echo. A client that presents no token, or another token, is sent the reason in a
+    /// message of its own and disconnected, as is a client whose first message exceeds the size set by
+    /// set_max_auth_size or does not arrive within the time set by set_auth_timeout.
+    /// UDP datagrams echoed under RFC 862 are not authenticated.
+    /// The change will have no effect until the next call to start.
+    pub fn set_tokens<I, T>(&mut self, tokens: I)
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.tokens = Some(Arc::new(tokens.into_iter().map(Into::into).collect()))
+    }
+
+    /// Requires every client to present one of the tokens in the given file, which holds one token per line.
+    /// Surrounding whitespace and blank lines are ignored. Otherwise behaves as set_tokens.
+    /// Returns an error if the file cannot be read, in which case the accepted tokens are unchanged.
+    pub fn load_tokens(&mut self, path: &Path) -> Result<(), EchoError> {
+        let contents = fs::read_to_string(path)?;
+        self.set_tokens(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|token| !token.is_empty()),
+        );
+        Ok(())
+    }
+
+    /// Stops requiring clients to present a token. The change will have no effect until the next call to start.
+    pub fn clear_tokens(&mut self) {
+        self.tokens = None
+    }
+
+    /// Sets the size, in bytes, of the largest first message in which a client can present its token, which is
+    /// 1 KiB by default. A client is disconnected as soon as it has sent more without completing the message.
+    /// The change will have no effect until the next call to start.
+    pub fn set_max_auth_size(&mut self, max_auth_size: usize) {
+        self.max_auth_size = max_auth_size
+    }
+
+    /// Sets the time a client has to present its token once it connects, including any TLS handshake,
+    /// which is 5 seconds by default. The change will have no effect until the next call to start.
+    pub fn set_auth_timeout(&mut self, auth_timeout: Duration) {
+        self.auth_timeout = auth_timeout
+    }
+
+    /// Sets which clients receive the echo of a message. Takes effect immediately, including for connected clients.
+    pub fn set_mode(&self, mode: EchoMode) {
+        *self.mode.lock().unwrap() = mode
+    }
+
+    /// Gets the choice of which clients receive the echo of a message.
+    pub fn mode(&self) -> EchoMode {
+        *self.mode.lock().unwrap()
+    }
+
+    /// Takes the most recent error reported while binding or communicating with clients,
+    /// if one has been reported since the last call.
+    pub fn take_error(&self) -> Option<EchoError> {
+        self.lifecycle.take_error()
     }
 
     /// Gets the flag that indicates if the server is currently running.
     pub fn is_running(&self) -> bool {
-        self.running.load(Ordering::Relaxed)
+        self.lifecycle.is_running()
     }
 
     /// Asynchronously starts the process of binding to the current address and accepting client connections.
-    /// If the server is already bound or attempting to bind, this method has no effect.
-    pub fn start(&mut self) {
-        if self.running.swap(true, Ordering::Relaxed) {
-            return;
+    /// Binding is retried with the configured backoff until it succeeds or the server is stopped.
+    /// Returns an error if the server is already bound or attempting to bind, or if the process could not be started.
+    /// Since binding happens in the background, a failure to bind is not returned here. Instead, each failed attempt
+    /// is passed to the handler's on_error as BindFailed and can be taken with take_error, and local_addr and
+    /// local_endpoint time out until an attempt succeeds.
+    pub fn start(&mut self) -> Result<(), EchoError> {
+        if !self.lifecycle.is_running() {
+            if let Some(handle) = self.accept_thread.take() {
+                // The previous accept thread stopped the server itself, and is exiting. It must be joined before
+                // the server runs again, so that its session threads do not resume and it does not release
+                // the acceptor bound by the new accept thread.
+                let _ = handle.join();
+            }
         }
-        let accept_address = self.address;
-        let accept_running = Arc::clone(&self.running);
-        thread::Builder::new()
+        self.lifecycle.begin()?;
+        let accept_address = self.address.clone();
+        let accept_acceptor = self.acceptor.clone();
+        let bind_delays = (self.bind_retry_delay, self.bind_retry_max_delay);
+        let accept_bound_acceptor = Arc::clone(&self.bound_acceptor);
+        let thread_model = self.thread_model;
+        let context = ServerContext {
+            lifecycle: self.lifecycle.clone(),
+            clients: Arc::clone(&self.clients),
+            outbox_capacity: self.outbox_capacity,
+            framing: self.framing,
+            rfc862: self.rfc862,
+            mode: Arc::clone(&self.mode),
+            handler: Arc::clone(&self.handler),
+            #[cfg(feature = "tls")]
+            tls: self.tls.config(),
+            tokens: self.tokens.clone(),
+            max_auth_size: self.max_auth_size,
+            auth_timeout: self.auth_timeout,
+        };
+        let spawn_result = thread::Builder::new()
             .name(String::from("JdnEcho-TcpListener-accept"))
             .spawn(move || {
-                let _ = Self::accept_process(accept_address, accept_running);
-            })
-            .expect("failed to spawn thread");
+                let acceptor = match accept_acceptor {
+                    Some(acceptor) => Some(acceptor),
+                    None => Self::bind_process(&accept_address, &context, bind_delays),
+                };
+                if let Some(acceptor) = acceptor {
+                    // Datagrams are only echoed alongside a TCP endpoint.
+                    let bound_address = acceptor
+                        .local_endpoint()
+                        .and_then(|endpoint| endpoint.socket_addr());
+                    let udp_thread = match bound_address {
+                        Some(bound_address) if context.rfc862 => {
+                            Self::spawn_udp_process(bound_address, &context)
+                        }
+                        _ => None,
+                    };
+                    accept_bound_acceptor.set(Some(Arc::clone(&acceptor)));
+                    match SessionRunner::new(thread_model, &context) {
+                        Ok(mut runner) => {
+                            if let Err(e) = Self::accept_process(
+                                acceptor.as_ref(),
+                                &context,
+                                &mut runner,
+                                bind_delays,
+                            ) {
+                                context.report(None, EchoError::Io(e));
+                            }
+                            // Nothing accepts clients any more, so the sessions must stop as well.
+                            context.lifecycle.end();
+                            runner.join();
+                        }
+                        Err(e) => {
+                            context.report(None, e);
+                            context.lifecycle.end();
+                        }
+                    }
+                    if let Some(handle) = udp_thread {
+                        let _ = handle.join();
+                    }
+                }
+                accept_bound_acceptor.set(None);
+            });
+        match spawn_result {
+            Ok(handle) => {
+                self.accept_thread = Some(handle);
+                Ok(())
+            }
+            Err(e) => {
+                self.lifecycle.end();
+                Err(EchoError::ThreadSpawnFailed(e))
+            }
+        }
     }
 
-    /// Asynchronously stops the server process.
+    /// Stops the server process and disconnects all clients.
+    /// Waits for all of the server's threads to exit before returning.
     pub fn stop(&mut self) {
-        self.running.store(false, Ordering::Relaxed)
-    }
-
-    fn accept_process(address: SocketAddr, accept_running: Arc<AtomicBool>) -> std::io::Result<()> {
-        while accept_running.load(Ordering::Relaxed) {
-            match TcpListener::bind(address) {
-                Ok(listener) => {
-                    listener.set_nonblocking(true)?;
-                    match listener.accept() {
-                        Ok((mut socket, addr)) => {
-                            println!("Accepted connection from {}", addr);
-                            if socket.set_nonblocking(true).is_err() {
-                                continue;
-                            }
-                            thread::Builder::new()
-                                .name(format!("JdnEcho-TcpListener-{}-read", addr))
-                                .spawn(move || {
-                                    let mut buf = Vec::new();
-                                    match socket.read_to_end(&mut buf) {
-                                        Ok(_) => {
-                                            if !buf.is_empty() {
-                                                match String::from_utf8(buf.drain(..).collect()) {
-                                                    Ok(data) => {
-                                                        println!("{}", data);
-                                                    }
-                                                    Err(e) => {
-                                                        println!("Could not parse data: {}", e);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        Err(e) => {
-                                            if e.kind() == io::ErrorKind::WouldBlock {
-                                                thread::sleep(Duration::from_millis(
-                                                    Self::DELAY_MS,
-                                                ));
-                                            } else {
-                                                // return Err(e);
-                                            }
-                                        }
-                                    }
-                                })
-                                .expect("failed to spawn thread");
-                        }
+        self.lifecycle.end();
+        if let Some(handle) = self.accept_thread.take() {
+            let _ = handle.join();
+        }
+    }
+
+    fn bind_process(
+        address: &Endpoint,
+        context: &ServerContext,
+        (mut delay, max_delay): (Duration, Duration),
+    ) -> Option<Arc<dyn Acceptor>> {
+        while context.lifecycle.is_running() {
+            match transport::bind(address) {
+                Ok(acceptor) => return Some(Arc::from(acceptor)),
+                Err(e) => {
+                    context.report(None, EchoError::BindFailed(e));
+                    context.lifecycle.sleep(delay);
+                    delay = (delay * 2).min(max_delay);
+                }
+            }
+        }
+        None
+    }
+
+    fn accept_process(
+        acceptor: &dyn Acceptor,
+        context: &ServerContext,
+        runner: &mut SessionRunner,
+        (initial_delay, max_delay): (Duration, Duration),
+    ) -> std::io::Result<()> {
+        let mut delay = initial_delay;
+        // The ID of the next client's connection, which tells it apart from clients given the same address.
+        let mut next_id: u64 = 0;
+        while context.lifecycle.is_running() {
+            match acceptor.accept() {
+                Ok((socket, addr)) => {
+                    delay = initial_delay;
+                    let id = next_id;
+                    next_id += 1;
+                    let socket = match context.client_stream(socket) {
+                        Ok(stream) => stream,
                         Err(e) => {
-                            if e.kind() == io::ErrorKind::WouldBlock {
-                                thread::sleep(Duration::from_millis(Self::DELAY_MS));
-                            } else {
-                                return Err(e);
-                            }
+                            context.report(Some(addr), EchoError::Io(e));
+                            continue;
                         }
+                    };
+                    let mut session = Session::new(socket, addr, id, context);
+                    // A client that must present a token is only registered once its session accepts the token.
+                    if context.tokens.is_none() {
+                        session.register(context);
+                    }
+                    context.handler.on_connect(addr);
+                    if let Err(e) = runner.run(session, context) {
+                        context.clients.lock().unwrap().remove(&id);
+                        context.report(Some(addr), e);
+                        context.handler.on_disconnect(addr);
                     }
                 }
-                Err(_) => thread::sleep(Duration::from_millis(Self::DELAY_MS)),
+                Err(e) => match e.kind() {
+                    io::ErrorKind::WouldBlock => {
+                        context.lifecycle.sleep(Duration::from_millis(DELAY_MS));
+                    }
+                    io::ErrorKind::Interrupted => {}
+                    // The acceptor itself is unusable, so no client can ever be accepted again.
+                    io::ErrorKind::InvalidInput | io::ErrorKind::Unsupported => return Err(e),
+                    // The error belongs to a connection that failed before it was accepted.
+                    io::ErrorKind::ConnectionAborted | io::ErrorKind::ConnectionReset => {
+                        context.report(None, EchoError::Io(e));
+                    }
+                    // Errors such as running out of file descriptors may pass once clients disconnect.
+                    _ => {
+                        context.report(None, EchoError::Io(e));
+                        context.lifecycle.sleep(delay);
+                        delay = (delay * 2).min(max_delay);
+                    }
+                },
             }
         }
         Ok(())
     }
+
+    fn spawn_udp_process(address: SocketAddr, context: &ServerContext) -> Option<JoinHandle<()>> {
+        let socket = match UdpSocket::bind(address).and_then(|socket| {
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        }) {
+            Ok(socket) => socket,
+            Err(e) => {
+                context.report(None, EchoError::BindFailed(e));
+                return None;
+            }
+        };
+        let udp_context = context.clone();
+        let spawn_result = thread::Builder::new()
+            .name(format!("JdnEcho-UdpSocket-{}-echo", address))
+            .spawn(move || Self::udp_process(socket, udp_context));
+        match spawn_result {
+            Ok(handle) => Some(handle),
+            Err(e) => {
+                context.report(None, EchoError::ThreadSpawnFailed(e));
+                None
+            }
+        }
+    }
+
+    fn udp_process(socket: UdpSocket, context: ServerContext) {
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        while context.lifecycle.is_running() {
+            match socket.recv_from(&mut buf) {
+                Ok((len, source)) => {
+                    // RFC 862 sends each datagram back to its source, unchanged.
+                    if let Err(e) = socket.send_to(&buf[..len], source) {
+                        context.report(Some(source), EchoError::Io(e));
+                    }
+                }
+                Err(e) => {
+                    if e.kind() == io::ErrorKind::WouldBlock {
+                        context.lifecycle.sleep(Duration::from_millis(DELAY_MS));
+                    } else {
+                        // Errors such as an unreachable port belong to a previous datagram's source,
+                        // so the socket remains usable.
+                        context.report(None, EchoError::Io(e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The acceptor from which a server is accepting clients, shared between the server and its accept thread.
+// Releasing a bound acceptor when the server stops closes it, removing any Unix domain socket file.
+#[derive(Default)]
+struct BoundAcceptor {
+    // The bound acceptor, if the server is currently bound.
+    acceptor: Mutex<Option<Arc<dyn Acceptor>>>,
+    // The Condvar notified whenever the bound acceptor changes.
+    changed: Condvar,
+}
+
+impl BoundAcceptor {
+    fn set(&self, acceptor: Option<Arc<dyn Acceptor>>) {
+        *self.acceptor.lock().unwrap() = acceptor;
+        self.changed.notify_all();
+    }
+
+    fn wait(
+        &self,
+        lifecycle: &Lifecycle,
+        timeout: Duration,
+    ) -> Result<Arc<dyn Acceptor>, EchoError> {
+        let deadline = Instant::now() + timeout;
+        let mut acceptor = self.acceptor.lock().unwrap();
+        loop {
+            if let Some(acceptor) = &*acceptor {
+                return Ok(Arc::clone(acceptor));
+            }
+            if !lifecycle.is_running() {
+                return Err(EchoError::NotRunning);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(EchoError::TimedOut);
+            }
+            // Wake periodically, since stopping the server before it binds does not change the acceptor.
+            let wait = (deadline - now).min(Duration::from_millis(DELAY_MS));
+            acceptor = self.changed.wait_timeout(acceptor, wait).unwrap().0;
+        }
+    }
+}
+
+impl EchoService for EchoServer {
+    fn set_address(&mut self, address: Endpoint) {
+        EchoServer::set_address(self, address)
+    }
+
+    fn is_running(&self) -> bool {
+        EchoServer::is_running(self)
+    }
+
+    fn start(&mut self) -> Result<(), EchoError> {
+        EchoServer::start(self)
+    }
+
+    fn stop(&mut self) {
+        EchoServer::stop(self)
+    }
+
+    fn take_error(&self) -> Option<EchoError> {
+        EchoServer::take_error(self)
+    }
+}
+
+impl Drop for EchoServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
 }
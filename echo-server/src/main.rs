@@ -1,14 +1,14 @@
-mod lib;
-
 use std::io::Write;
-use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
 use jdn_cli::manager::CliManager;
 use jdn_cli::{CliError, CliHandler};
+use jdn_echo_core::cli;
 
-use crate::lib::EchoServer;
+use jdn_echo_server::{ConsoleHandler, EchoMode, EchoServer, Endpoint};
 
 fn main() {
     let echo_handler = Arc::new(EchoCliHandler::new());
@@ -17,15 +17,20 @@ fn main() {
     cli_manager.start();
 }
 
-const SET_ADDRESS_COMMAND: &str = "set-address";
-const IS_RUNNING_COMMAND: &str = "is-running";
-const START_COMMAND: &str = "start";
-const STOP_COMMAND: &str = "stop";
-const COMMANDS: [&str; 4] = [
-    SET_ADDRESS_COMMAND,
-    IS_RUNNING_COMMAND,
-    START_COMMAND,
-    STOP_COMMAND,
+const SET_MODE_COMMAND: &str = "set-mode";
+const MODE_COMMAND: &str = "mode";
+const SET_RFC862_COMMAND: &str = "set-rfc862";
+const COMMANDS: [&str; 3] = [SET_MODE_COMMAND, MODE_COMMAND, SET_RFC862_COMMAND];
+#[cfg(feature = "tls")]
+const CLEAR_TLS_CERT_COMMAND: &str = "clear-tls-cert";
+#[cfg(feature = "tls")]
+const CLEAR_TLS_CA_COMMAND: &str = "clear-tls-ca";
+#[cfg(feature = "tls")]
+const TLS_COMMANDS: [&str; 4] = [
+    cli::SET_TLS_CERT_COMMAND,
+    cli::SET_TLS_CA_COMMAND,
+    CLEAR_TLS_CERT_COMMAND,
+    CLEAR_TLS_CA_COMMAND,
 ];
 
 struct EchoCliHandler {
@@ -36,7 +41,8 @@ impl EchoCliHandler {
     pub fn new() -> Self {
         EchoCliHandler {
             server: Mutex::new(EchoServer::new(
-                SocketAddr::from_str("0.0.0.0:8080").unwrap(),
+                Endpoint::from_str("0.0.0.0:8080").unwrap(),
+                Arc::new(ConsoleHandler),
             )),
         }
     }
@@ -44,7 +50,10 @@ impl EchoCliHandler {
 
 impl CliHandler for EchoCliHandler {
     fn get_commands(&self) -> std::collections::HashSet<&'static str> {
-        COMMANDS.iter().cloned().collect()
+        let commands = cli::COMMON_COMMANDS.iter().chain(COMMANDS.iter());
+        #[cfg(feature = "tls")]
+        let commands = commands.chain(TLS_COMMANDS.iter());
+        commands.cloned().collect()
     }
 
     fn handle_command(
@@ -53,31 +62,52 @@ impl CliHandler for EchoCliHandler {
         args: Vec<String>,
         writer: &mut dyn Write,
     ) -> Result<(), jdn_cli::CliError> {
+        if let Some(result) = cli::handle_common_command(&self.server, command, &args, writer) {
+            return result;
+        }
         match command {
-            SET_ADDRESS_COMMAND => {
-                if let Some(address) = args.get(0) {
-                    self.server.lock().unwrap().set_address(
-                        SocketAddr::from_str(address)
-                            .map_err(|e| CliError::ArgumentParseFailure(e.to_string()))?,
-                    );
-                } else {
-                    return Err(CliError::InvalidNumberOfArguments {
-                        min: 1,
-                        max: None,
-                        given: 0,
-                    });
-                }
+            SET_MODE_COMMAND => {
+                let mode = EchoMode::from_str(cli::first_argument(&args)?)
+                    .map_err(CliError::ArgumentParseFailure)?;
+                self.server.lock().unwrap().set_mode(mode);
+            }
+            SET_RFC862_COMMAND => {
+                let rfc862 = bool::from_str(cli::first_argument(&args)?)
+                    .map_err(|e| CliError::ArgumentParseFailure(e.to_string()))?;
+                self.server.lock().unwrap().set_rfc862(rfc862);
+            }
+            MODE_COMMAND => {
+                cli::write_output(writer, self.server.lock().unwrap().mode())?;
+            }
+            #[cfg(feature = "tls")]
+            cli::SET_TLS_CERT_COMMAND => {
+                let (cert_path, key_path) = cli::two_arguments(&args)?;
+                self.server
+                    .lock()
+                    .unwrap()
+                    .set_tls_certificate(Path::new(cert_path), Path::new(key_path))
+                    .map_err(|e| CliError::ExecutionError(e.to_string()))?;
             }
-            IS_RUNNING_COMMAND => {
-                writeln!(writer, "{}", self.server.lock().unwrap().is_running()).map_err(|_| {
-                    CliError::ExecutionError(String::from("Unable to write output"))
-                })?;
+            #[cfg(feature = "tls")]
+            cli::SET_TLS_CA_COMMAND => {
+                let ca_path = cli::first_argument(&args)?;
+                self.server
+                    .lock()
+                    .unwrap()
+                    .set_tls_client_ca(Path::new(ca_path))
+                    .map_err(|e| CliError::ExecutionError(e.to_string()))?;
             }
-            START_COMMAND => {
-                self.server.lock().unwrap().start();
+            #[cfg(feature = "tls")]
+            CLEAR_TLS_CERT_COMMAND => {
+                self.server.lock().unwrap().clear_tls_certificate();
             }
-            STOP_COMMAND => {
-                self.server.lock().unwrap().stop();
+            #[cfg(feature = "tls")]
+            CLEAR_TLS_CA_COMMAND => {
+                self.server
+                    .lock()
+                    .unwrap()
+                    .clear_tls_client_ca()
+                    .map_err(|e| CliError::ExecutionError(e.to_string()))?;
             }
             _ => {
                 return Err(CliError::ExecutionError(format!(
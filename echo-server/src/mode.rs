@@ -0,0 +1,56 @@
+//! The choice of which clients receive the echo of a message.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Determines which connected clients receive the echo of a message sent by one of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum EchoMode {
+    /// Echo each message back to the client that sent it only, as described in RFC 862.
+    Sender,
+    /// Echo each message to every connected client, including the one that sent it.
+    #[default]
+    Broadcast,
+    /// Echo each message to every connected client except the one that sent it.
+    BroadcastExceptSender,
+}
+
+impl EchoMode {
+    /// Gets the flag that indicates if the given recipient should receive a message sent by the given sender,
+    /// where each client is identified by its address or any other value unique to it.
+    pub fn delivers_to<T: PartialEq>(self, sender: T, recipient: T) -> bool {
+        match self {
+            EchoMode::Sender => recipient == sender,
+            EchoMode::Broadcast => true,
+            EchoMode::BroadcastExceptSender => recipient != sender,
+        }
+    }
+}
+
+impl fmt::Display for EchoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EchoMode::Sender => "sender",
+            EchoMode::Broadcast => "broadcast",
+            EchoMode::BroadcastExceptSender => "broadcast-except-sender",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for EchoMode {
+    type Err = String;
+
+    /// Parses the names produced by Display, such as "broadcast-except-sender".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sender" => Ok(EchoMode::Sender),
+            "broadcast" => Ok(EchoMode::Broadcast),
+            "broadcast-except-sender" => Ok(EchoMode::BroadcastExceptSender),
+            _ => Err(format!(
+                "unknown echo mode '{}', expected sender, broadcast or broadcast-except-sender",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! The threads that serve the sessions of connected clients.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::protocol::{BUFFER_SIZE, DELAY_MS};
+
+use crate::session::{Poll, ServerContext, Session};
+
+// The time a session thread first waits once its connections have no data. The wait doubles while they stay idle,
+// up to DELAY_MS, so that data arriving soon after other data is not left waiting for a full delay.
+const MIN_IDLE_DELAY_MS: u64 = 1;
+
+/// The way an EchoServer assigns threads to its connected clients.
+/// Connections are polled rather than waited on, so whichever model is used, a thread whose connections have all been
+/// idle sleeps for a delay that doubles up to DELAY_MS in the protocol module before polling them again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThreadModel {
+    /// Serve each client on its own thread. The number of threads grows with the number of clients.
+    #[default]
+    PerConnection,
+    /// Serve all clients on the given number of worker threads, each of which polls many connections.
+    /// New clients are assigned to the worker serving the fewest clients.
+    /// Every connection of a worker is read on each of its polls, so the CPU it spends grows with the number of idle
+    /// connections, and the first message of a client may wait up to DELAY_MS to be echoed once the worker is idle.
+    /// Measure the trade-off with `cargo bench -p jdn-echo-server`.
+    Pool(usize),
+}
+
+/// Runs the sessions of connected clients on threads, according to a ThreadModel.
+pub(crate) enum SessionRunner {
+    PerConnection(Vec<JoinHandle<()>>),
+    Pool(Vec<Worker>),
+}
+
+/// A worker thread of a pool, along with the channel used to hand it new sessions.
+pub(crate) struct Worker {
+    // The Sender used to hand new sessions to the worker.
+    sender: mpsc::Sender<Session>,
+    // The number of sessions the worker is serving.
+    load: Arc<AtomicUsize>,
+    // The handle of the worker thread, joined when the server is stopped.
+    handle: JoinHandle<()>,
+}
+
+impl SessionRunner {
+    /// Constructs a new SessionRunner, spawning the worker threads of a pool.
+    pub(crate) fn new(model: ThreadModel, context: &ServerContext) -> Result<Self, EchoError> {
+        match model {
+            ThreadModel::PerConnection => Ok(SessionRunner::PerConnection(Vec::new())),
+            ThreadModel::Pool(size) => {
+                let mut workers = Vec::with_capacity(size);
+                for index in 0..size.max(1) {
+                    let (sender, receiver) = mpsc::channel();
+                    let load = Arc::new(AtomicUsize::new(0));
+                    let worker_load = Arc::clone(&load);
+                    let worker_context = context.clone();
+                    let spawn_result = thread::Builder::new()
+                        .name(format!("JdnEcho-TcpListener-worker-{}", index))
+                        .spawn(move || worker_process(receiver, worker_load, worker_context));
+                    match spawn_result {
+                        Ok(handle) => workers.push(Worker {
+                            sender,
+                            load,
+                            handle,
+                        }),
+                        Err(e) => {
+                            // Stop the workers already spawned, which would otherwise run until the server stops.
+                            SessionRunner::Pool(workers).join();
+                            return Err(EchoError::ThreadSpawnFailed(e));
+                        }
+                    }
+                }
+                Ok(SessionRunner::Pool(workers))
+            }
+        }
+    }
+
+    /// Starts serving the given session. If this fails, the session has been dropped without being closed.
+    pub(crate) fn run(
+        &mut self,
+        session: Session,
+        context: &ServerContext,
+    ) -> Result<(), EchoError> {
+        match self {
+            SessionRunner::PerConnection(threads) => {
+                threads.retain(|handle| !handle.is_finished());
+                let read_context = context.clone();
+                let handle = thread::Builder::new()
+                    .name(format!("JdnEcho-TcpListener-{}-read", session.addr()))
+                    .spawn(move || read_process(session, read_context))
+                    .map_err(EchoError::ThreadSpawnFailed)?;
+                threads.push(handle);
+                Ok(())
+            }
+            SessionRunner::Pool(workers) => {
+                let worker = workers
+                    .iter()
+                    .min_by_key(|worker| worker.load.load(Ordering::Relaxed))
+                    .ok_or(EchoError::NotRunning)?;
+                worker.load.fetch_add(1, Ordering::Relaxed);
+                worker
+                    .sender
+                    .send(session)
+                    .map_err(|_| EchoError::NotRunning)
+            }
+        }
+    }
+
+    /// Waits for every session thread to exit. Sessions are closed once the server's lifecycle ends.
+    pub(crate) fn join(self) {
+        match self {
+            SessionRunner::PerConnection(threads) => {
+                for handle in threads {
+                    let _ = handle.join();
+                }
+            }
+            SessionRunner::Pool(workers) => {
+                for worker in workers {
+                    std::mem::drop(worker.sender);
+                    let _ = worker.handle.join();
+                }
+            }
+        }
+    }
+}
+
+fn read_process(mut session: Session, context: ServerContext) {
+    let mut buf = [0; BUFFER_SIZE];
+    let mut idle_delay = MIN_IDLE_DELAY_MS;
+    while context.lifecycle.is_running() {
+        match session.poll(&mut buf, &context) {
+            Poll::Received => idle_delay = MIN_IDLE_DELAY_MS,
+            Poll::Idle => idle_delay = idle_sleep(idle_delay, &context),
+            Poll::Closed => break,
+        }
+    }
+    session.close(&context);
+}
+
+fn worker_process(
+    receiver: mpsc::Receiver<Session>,
+    load: Arc<AtomicUsize>,
+    context: ServerContext,
+) {
+    let mut sessions: Vec<Session> = Vec::new();
+    let mut buf = [0; BUFFER_SIZE];
+    let mut idle_delay = MIN_IDLE_DELAY_MS;
+    while context.lifecycle.is_running() {
+        match receiver.try_recv() {
+            Ok(session) => sessions.push(session),
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => break,
+        }
+        sessions.extend(receiver.try_iter());
+        let mut received = false;
+        let mut index = 0;
+        while index < sessions.len() {
+            match sessions[index].poll(&mut buf, &context) {
+                Poll::Received => {
+                    received = true;
+                    index += 1;
+                }
+                Poll::Idle => index += 1,
+                Poll::Closed => {
+                    sessions.swap_remove(index).close(&context);
+                    load.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+        }
+        if received {
+            idle_delay = MIN_IDLE_DELAY_MS;
+        } else {
+            idle_delay = idle_sleep(idle_delay, &context);
+        }
+    }
+    for session in sessions.into_iter().chain(receiver.try_iter()) {
+        session.close(&context);
+    }
+}
+
+// Sleeps for the given number of milliseconds, and returns the delay to use if the connections are still idle after.
+fn idle_sleep(delay: u64, context: &ServerContext) -> u64 {
+    context.lifecycle.sleep(Duration::from_millis(delay));
+    (delay * 2).min(DELAY_MS)
+}
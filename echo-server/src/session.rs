@@ -0,0 +1,367 @@
+//! The handling of the messages received from a connected client.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tls")]
+use rustls::ServerConfig;
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::framing::{FrameDecoder, Framing};
+use jdn_echo_core::lifecycle::Lifecycle;
+use jdn_echo_core::protocol;
+use jdn_echo_core::transport::Transport;
+
+use crate::handler::{self, EchoServerHandler};
+use crate::mode::EchoMode;
+use crate::stream::ClientStream;
+
+/// The outboxes of all currently connected clients, keyed by the ID of their connection. Clients of an acceptor
+/// without addresses share placeholder addresses once enough have connected, so an address may not be unique.
+pub(crate) type Clients = Arc<Mutex<HashMap<u64, Outbox>>>;
+
+/// The bounded queue through which echoes are handed to the session of a connected client, which writes them to
+/// the client. Only the session writes to its client, so a client that is slow to read never holds up the others.
+pub(crate) struct Outbox {
+    // The address of the client, with which an overflow is reported.
+    addr: SocketAddr,
+    // The Sender used to queue frames for the session.
+    sender: mpsc::SyncSender<Vec<u8>>,
+    // The flag set once the queue has overflowed, which tells the session to disconnect the client.
+    overflowed: Arc<AtomicBool>,
+}
+
+/// The state shared between a server and its background threads.
+#[derive(Clone)]
+pub(crate) struct ServerContext {
+    // The running state of the server, through which threads learn that it stopped and report their errors.
+    pub(crate) lifecycle: Lifecycle,
+    // The outboxes of all currently connected clients, keyed by the ID of their connection.
+    pub(crate) clients: Clients,
+    // The number of echoes that can be queued for a client before it is disconnected.
+    pub(crate) outbox_capacity: usize,
+    // The framing used to delimit messages sent to and received from clients.
+    pub(crate) framing: Framing,
+    // The flag that indicates if the server follows RFC 862.
+    pub(crate) rfc862: bool,
+    // The choice of which clients receive the echo of a message.
+    pub(crate) mode: Arc<Mutex<EchoMode>>,
+    // The handler notified of connections, messages and errors.
+    pub(crate) handler: Arc<dyn EchoServerHandler>,
+    // The tokens of which clients must present one before they are served, or None if clients are not authenticated.
+    pub(crate) tokens: Option<Arc<HashSet<String>>>,
+    // The largest message in which a client can present its token.
+    pub(crate) max_auth_size: usize,
+    // The time a client has to present its token once it connects, including any TLS handshake.
+    pub(crate) auth_timeout: Duration,
+    // The TLS configuration used to serve clients, or None to serve them in plaintext.
+    #[cfg(feature = "tls")]
+    pub(crate) tls: Option<Arc<ServerConfig>>,
+}
+
+impl ServerContext {
+    /// Wraps an accepted socket in a non-blocking stream, using TLS if the server is configured for it.
+    pub(crate) fn client_stream(&self, socket: Box<dyn Transport>) -> io::Result<ClientStream> {
+        #[cfg(feature = "tls")]
+        if let Some(config) = &self.tls {
+            return ClientStream::tls(socket, config);
+        }
+        ClientStream::plain(socket)
+    }
+
+    /// Notifies the handler of the given error, and records it as the server's most recent error.
+    pub(crate) fn report(&self, peer: Option<SocketAddr>, error: EchoError) {
+        self.handler.on_error(peer, &error);
+        self.lifecycle.report(error);
+    }
+}
+
+/// The result of polling a Session for data.
+pub(crate) enum Poll {
+    /// Data was received and handled, or sent to the client.
+    Received,
+    /// No data was available, and none could be sent.
+    Idle,
+    /// The client disconnected, or the connection failed.
+    Closed,
+}
+
+/// A connected client, along with the state needed to split its data into messages.
+pub(crate) struct Session {
+    // The non-blocking stream from which the client's data is read, and to which its echoes are written.
+    socket: ClientStream,
+    // The address of the client.
+    addr: SocketAddr,
+    // The ID of the client's connection, which unlike its address is never shared with another client.
+    id: u64,
+    // The outbox through which the client receives echoes, until the client is registered with the context.
+    outbox: Option<Outbox>,
+    // The Receiver of the frames queued in the client's outbox.
+    outgoing: mpsc::Receiver<Vec<u8>>,
+    // The flag set once the client's outbox has overflowed.
+    overflowed: Arc<AtomicBool>,
+    // The data being written to the client, which the socket has not yet taken.
+    pending: Vec<u8>,
+    // The decoder holding any partial message received from the client.
+    decoder: FrameDecoder,
+    // The flag that indicates if the client's certificate has been checked for a subject to report to the handler.
+    identified: bool,
+    // The flag that indicates if the client has presented an accepted token, or does not need to.
+    authenticated: bool,
+    // The time by which the client must have presented its token.
+    auth_deadline: Instant,
+    // The number of bytes received before the client's token was complete.
+    auth_received: usize,
+}
+
+impl Session {
+    /// Constructs a new Session for the given client, whose connection has the given unique ID. Unless the context requires a token, the client must be
+    /// registered with the context before the session is run; otherwise it is registered once it presents an
+    /// accepted token.
+    pub(crate) fn new(
+        socket: ClientStream,
+        addr: SocketAddr,
+        id: u64,
+        context: &ServerContext,
+    ) -> Self {
+        let (sender, outgoing) = mpsc::sync_channel(context.outbox_capacity.max(1));
+        let overflowed = Arc::new(AtomicBool::new(false));
+        Session {
+            socket,
+            addr,
+            id,
+            outbox: Some(Outbox {
+                addr,
+                sender,
+                overflowed: Arc::clone(&overflowed),
+            }),
+            outgoing,
+            overflowed,
+            pending: Vec::new(),
+            decoder: context.framing.decoder(),
+            identified: false,
+            authenticated: context.tokens.is_none(),
+            auth_deadline: Instant::now() + context.auth_timeout,
+            auth_received: 0,
+        }
+    }
+
+    /// Gets the address of the client.
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Adds the client's outbox to the context, so that it receives the echoes of messages.
+    pub(crate) fn register(&mut self, context: &ServerContext) {
+        if let Some(outbox) = self.outbox.take() {
+            context.clients.lock().unwrap().insert(self.id, outbox);
+        }
+    }
+
+    /// Writes as much of the client's queued echoes as it will take, then reads any available data from the client
+    /// into the given buffer, and echoes every message it completes. A client that has not presented its token by its
+    /// deadline is denied, and a client whose outbox has overflowed is closed.
+    pub(crate) fn poll(&mut self, buf: &mut [u8], context: &ServerContext) -> Poll {
+        // The overflow was reported when the outbox was removed from the context.
+        if self.overflowed.load(Ordering::Relaxed) {
+            return Poll::Closed;
+        }
+        let sent = match self.send_pending() {
+            Ok(sent) => sent,
+            Err(e) => {
+                context.report(Some(self.addr), EchoError::Io(e));
+                return Poll::Closed;
+            }
+        };
+        let idle = if sent { Poll::Received } else { Poll::Idle };
+        // RFC 862 reads nothing more from a client until what it sent has been echoed back to it.
+        if context.rfc862 && !self.pending.is_empty() {
+            return idle;
+        }
+        let result = self.socket.read(buf);
+        if !self.identified && !self.socket.is_handshaking() {
+            // Every read advances the handshake, so the client is identified as soon as the handshake completes,
+            // whether or not it has sent any data.
+            self.identified = true;
+            if let Some(subject) = self.socket.peer_subject() {
+                context.handler.on_authenticate(self.addr, &subject);
+            }
+        }
+        match result {
+            Ok(0) => Poll::Closed,
+            Ok(len) if !self.authenticated => self.authenticate(&buf[..len], context),
+            Ok(len) if context.rfc862 => self.echo_verbatim(&buf[..len], context),
+            Ok(len) => match self.decoder.decode(&buf[..len]) {
+                Ok(messages) => {
+                    self.handle_messages(messages, context);
+                    Poll::Received
+                }
+                Err(e) => {
+                    context.report(Some(self.addr), e);
+                    Poll::Closed
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if !self.authenticated && Instant::now() >= self.auth_deadline {
+                    return self.deny("token not presented in time", context);
+                }
+                idle
+            }
+            Err(e) => {
+                context.report(Some(self.addr), EchoError::Io(e));
+                Poll::Closed
+            }
+        }
+    }
+
+    // Checks the token presented in the client's first message. The message is framed even under RFC 862.
+    // An accepted client is told so before it is registered with the context, so that no echo can reach it first.
+    // Any data received after its token is then handled as usual.
+    fn authenticate(&mut self, data: &[u8], context: &ServerContext) -> Poll {
+        self.decoder.extend(data);
+        self.auth_received += data.len();
+        let first = match self.decoder.next_message() {
+            Ok(Some(message)) => message,
+            // Everything received so far belongs to the incomplete first message.
+            Ok(None) if self.auth_received <= context.max_auth_size => return Poll::Received,
+            Ok(None) | Err(_) => return self.deny("token too long", context),
+        };
+        if first.len() > context.max_auth_size {
+            return self.deny("token too long", context);
+        }
+        let reason = match (protocol::auth_token(&first), &context.tokens) {
+            (Some(token), Some(tokens)) if tokens.contains(token) => None,
+            (Some(_), _) => Some("token not accepted"),
+            (None, _) => Some("token required"),
+        };
+        if let Some(reason) = reason {
+            return self.deny(reason, context);
+        }
+        match context.framing.encode(protocol::ACCEPTED) {
+            Ok(accepted) => self.pending = accepted,
+            Err(e) => {
+                context.report(Some(self.addr), e);
+                return Poll::Closed;
+            }
+        }
+        self.register(context);
+        self.authenticated = true;
+        if context.rfc862 {
+            let rest = self.decoder.take_pending();
+            return self.echo_verbatim(&rest, context);
+        }
+        match self.decoder.messages() {
+            Ok(messages) => {
+                self.handle_messages(messages, context);
+                Poll::Received
+            }
+            Err(e) => {
+                context.report(Some(self.addr), e);
+                Poll::Closed
+            }
+        }
+    }
+
+    // Tells the client why it failed to authenticate, and closes the connection.
+    fn deny(&mut self, reason: &str, context: &ServerContext) -> Poll {
+        context.report(
+            Some(self.addr),
+            EchoError::AuthenticationFailed(String::from(reason)),
+        );
+        // The denial is sent only if the socket takes it at once, since the client is about to be closed.
+        if let Ok(denial) = context.framing.encode(&protocol::denial(reason)) {
+            self.pending = denial;
+            let _ = self.send_pending();
+        }
+        Poll::Closed
+    }
+
+    // RFC 862 echoes whatever was received back to the sender, byte for byte.
+    fn echo_verbatim(&mut self, data: &[u8], context: &ServerContext) -> Poll {
+        self.pending.extend_from_slice(data);
+        match self.send_pending() {
+            Ok(_) => Poll::Received,
+            Err(e) => {
+                context.report(Some(self.addr), EchoError::Io(e));
+                Poll::Closed
+            }
+        }
+    }
+
+    // Writes pending data to the client, followed by the frames queued in its outbox, until the socket would block.
+    // Returns whether anything was written.
+    fn send_pending(&mut self) -> io::Result<bool> {
+        let mut sent = false;
+        loop {
+            if self.pending.is_empty() {
+                match self.outgoing.try_recv() {
+                    Ok(frame) => self.pending = frame,
+                    Err(_) => return Ok(sent),
+                }
+            }
+            match self.socket.write(&self.pending) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                Ok(len) => {
+                    self.pending.drain(..len);
+                    sent = true;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(sent),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn handle_messages(&self, messages: Vec<Vec<u8>>, context: &ServerContext) {
+        for message in messages {
+            let reply = handler::handle_bytes(context.handler.as_ref(), self.addr, message);
+            match reply.map(|data| context.framing.encode(&data)) {
+                Some(Ok(frame)) => echo(context, self.id, &frame),
+                Some(Err(e)) => context.report(Some(self.addr), e),
+                None => {}
+            }
+        }
+    }
+
+    /// Removes the client from the connected clients, closes its stream, and notifies the handler that it disconnected.
+    /// The stream is closed here rather than left to be dropped, so that a TLS session is ended cleanly.
+    pub(crate) fn close(mut self, context: &ServerContext) {
+        context.clients.lock().unwrap().remove(&self.id);
+        self.socket.shutdown();
+        context.handler.on_disconnect(self.addr);
+    }
+}
+
+// Queues the given data for every client that should receive it, to be written by the client's own session.
+// A client whose outbox is full is not keeping up with its echoes, so its outbox is removed, which makes its session
+// disconnect it.
+fn echo(context: &ServerContext, sender: u64, data: &[u8]) {
+    let mode = *context.mode.lock().unwrap();
+    let mut clients = context.clients.lock().unwrap();
+    let overflowed: Vec<u64> = clients
+        .iter()
+        .filter(|(id, _)| mode.delivers_to(sender, **id))
+        .filter_map(|(id, outbox)| match outbox.sender.try_send(data.to_vec()) {
+            Err(TrySendError::Full(_)) => Some(*id),
+            // A closing session removes its outbox itself.
+            Ok(()) | Err(TrySendError::Disconnected(_)) => None,
+        })
+        .collect();
+    let overflowed: Vec<SocketAddr> = overflowed
+        .iter()
+        .filter_map(|id| clients.remove(id))
+        .map(|outbox| {
+            outbox.overflowed.store(true, Ordering::Relaxed);
+            outbox.addr
+        })
+        .collect();
+    drop(clients);
+    for addr in overflowed {
+        context.report(Some(addr), EchoError::OutboxFull);
+    }
+}
@@ -0,0 +1,151 @@
+//! The streams over which a server exchanges data with its clients.
+
+use std::io;
+use std::io::{Read, Write};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+use rustls::{ServerConfig, ServerConnection};
+
+#[cfg(feature = "tls")]
+use jdn_echo_core::tls;
+use jdn_echo_core::transport::Transport;
+
+#[cfg(feature = "tls")]
+use crate::tls::certificate_subject;
+
+/// A non-blocking stream to a connected client, either plaintext or TLS.
+pub(crate) enum ClientStream {
+    Plain(Box<dyn Transport>),
+    #[cfg(feature = "tls")]
+    Tls {
+        socket: Box<dyn Transport>,
+        session: Box<ServerConnection>,
+    },
+}
+
+impl ClientStream {
+    /// Constructs a new plaintext ClientStream from an accepted socket, making it non-blocking.
+    pub(crate) fn plain(socket: Box<dyn Transport>) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(ClientStream::Plain(socket))
+    }
+
+    /// Constructs a new TLS ClientStream from an accepted socket, making it non-blocking.
+    /// The handshake is completed by the first reads from the stream.
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls(socket: Box<dyn Transport>, config: &Arc<ServerConfig>) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let session = ServerConnection::new(Arc::clone(config)).map_err(tls::io_error)?;
+        Ok(ClientStream::Tls {
+            socket,
+            session: Box::new(session),
+        })
+    }
+
+    /// Closes the stream, ending a TLS session cleanly first if possible.
+    pub(crate) fn shutdown(&mut self) {
+        #[cfg(feature = "tls")]
+        if let ClientStream::Tls { socket, session } = self {
+            session.send_close_notify();
+            let _ = tls::flush_tls(session, socket);
+        }
+        let _ = self.socket().shutdown();
+    }
+
+    /// Gets the flag that indicates if the TLS handshake with the client is still in progress.
+    /// A plaintext stream has no handshake.
+    pub(crate) fn is_handshaking(&self) -> bool {
+        match self {
+            ClientStream::Plain(_) => false,
+            #[cfg(feature = "tls")]
+            ClientStream::Tls { session, .. } => session.is_handshaking(),
+        }
+    }
+
+    /// Gets the subject of the certificate presented by the client, once the TLS handshake has completed.
+    /// Returns None for a plaintext stream, or if the client has not presented a certificate.
+    pub(crate) fn peer_subject(&self) -> Option<String> {
+        match self {
+            ClientStream::Plain(_) => None,
+            #[cfg(feature = "tls")]
+            ClientStream::Tls { session, .. } => {
+                if session.is_handshaking() {
+                    return None;
+                }
+                session
+                    .peer_certificates()
+                    .and_then(|certificates| certificates.first())
+                    .and_then(certificate_subject)
+            }
+        }
+    }
+
+    fn socket(&self) -> &dyn Transport {
+        match self {
+            ClientStream::Plain(socket) => socket.as_ref(),
+            #[cfg(feature = "tls")]
+            ClientStream::Tls { socket, .. } => socket.as_ref(),
+        }
+    }
+}
+
+impl Read for ClientStream {
+    /// Reads the next available data. A TLS stream returns WouldBlock until a complete record has been received.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(socket) => socket.read(buf),
+            #[cfg(feature = "tls")]
+            ClientStream::Tls { socket, session } => {
+                loop {
+                    // Send anything left over from an earlier write, or required by the handshake.
+                    match tls::flush_tls(session, socket) {
+                        Err(e) if e.kind() != io::ErrorKind::WouldBlock => return Err(e),
+                        _ => {}
+                    }
+                    match session.reader().read(buf) {
+                        Ok(len) => return Ok(len),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(e) => return Err(e),
+                    }
+                    if session.read_tls(socket)? == 0 {
+                        return Ok(0);
+                    }
+                    if let Err(e) = session.process_new_packets() {
+                        // Try to tell the client why the connection is being closed.
+                        let _ = tls::flush_tls(session, socket);
+                        return Err(tls::io_error(e));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Write for ClientStream {
+    /// Writes the given data. A TLS stream accepts all of the data once any earlier data has been sent,
+    /// and sends as much of it as the socket will take, leaving the rest to be sent by later reads and writes.
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(socket) => socket.write(data),
+            #[cfg(feature = "tls")]
+            ClientStream::Tls { socket, session } => {
+                tls::flush_tls(session, socket)?;
+                let len = session.writer().write(data)?;
+                match tls::flush_tls(session, socket) {
+                    Err(e) if e.kind() != io::ErrorKind::WouldBlock => Err(e),
+                    _ => Ok(len),
+                }
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ClientStream::Plain(socket) => socket.flush(),
+            #[cfg(feature = "tls")]
+            ClientStream::Tls { socket, session } => tls::flush_tls(session, socket),
+        }
+    }
+}
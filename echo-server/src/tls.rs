@@ -0,0 +1,114 @@
+//! The TLS configuration of an EchoServer: the certificate it presents, and how it authenticates its clients.
+//! Available with the `tls` feature.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::tls::{self, clone_identity, Identity};
+
+/// The choices that make up a server's TLS configuration, along with the configuration built from them.
+#[derive(Default)]
+pub(crate) struct TlsSettings {
+    // The certificate chain and private key presented to clients, or None to serve clients in plaintext.
+    identity: Option<Identity>,
+    // The CA certificates to which a client's certificate must chain, or None if clients are not authenticated.
+    client_roots: Option<Arc<RootCertStore>>,
+    // The configuration built from the current choices.
+    config: Option<Arc<ServerConfig>>,
+}
+
+impl TlsSettings {
+    /// Presents the certificate chain and private key in the given PEM files to clients.
+    pub(crate) fn set_certificate(
+        &mut self,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> Result<(), EchoError> {
+        let identity = (
+            tls::load_certificates(cert_path)?,
+            tls::load_private_key(key_path)?,
+        );
+        self.update(Some(identity), self.client_roots.clone())
+    }
+
+    /// Requires every client to present a certificate that chains to one of the CA certificates in the given PEM file.
+    pub(crate) fn set_client_ca(&mut self, ca_path: &Path) -> Result<(), EchoError> {
+        let mut roots = RootCertStore::empty();
+        for certificate in tls::load_certificates(ca_path)? {
+            roots
+                .add(certificate)
+                .map_err(|e| EchoError::TlsConfigFailed(format!("{}: {}", ca_path.display(), e)))?;
+        }
+        let identity = self.identity.as_ref().map(clone_identity);
+        self.update(identity, Some(Arc::new(roots)))
+    }
+
+    /// Serves clients in plaintext, keeping any CA certificates for when a certificate is next presented.
+    pub(crate) fn clear_certificate(&mut self) {
+        self.identity = None;
+        self.config = None;
+    }
+
+    /// Stops requiring clients to present a certificate.
+    pub(crate) fn clear_client_ca(&mut self) -> Result<(), EchoError> {
+        let identity = self.identity.as_ref().map(clone_identity);
+        self.update(identity, None)
+    }
+
+    /// Gets the configuration built from the current choices, or None if clients should be served in plaintext.
+    pub(crate) fn config(&self) -> Option<Arc<ServerConfig>> {
+        self.config.clone()
+    }
+
+    // Builds the configuration for the given choices, keeping them only if it is valid.
+    fn update(
+        &mut self,
+        identity: Option<Identity>,
+        client_roots: Option<Arc<RootCertStore>>,
+    ) -> Result<(), EchoError> {
+        self.config = match &identity {
+            Some(identity) => Some(build_config(identity, client_roots.as_ref())?),
+            None => None,
+        };
+        self.identity = identity;
+        self.client_roots = client_roots;
+        Ok(())
+    }
+}
+
+/// Gets the subject of the given certificate, as a comma-separated list of its attributes,
+/// such as "CN=client, O=Example". Returns None if the certificate cannot be parsed.
+pub(crate) fn certificate_subject(certificate: &CertificateDer<'_>) -> Option<String> {
+    x509_parser::parse_x509_certificate(certificate.as_ref())
+        .ok()
+        .map(|(_, certificate)| certificate.subject().to_string())
+}
+
+fn build_config(
+    identity: &Identity,
+    client_roots: Option<&Arc<RootCertStore>>,
+) -> Result<Arc<ServerConfig>, EchoError> {
+    let provider = tls::crypto_provider();
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| EchoError::TlsConfigFailed(e.to_string()))?;
+    let builder = match client_roots {
+        Some(roots) => {
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::clone(roots), provider)
+                .build()
+                .map_err(|e| EchoError::TlsConfigFailed(e.to_string()))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let (certificates, key) = clone_identity(identity);
+    let config = builder
+        .with_single_cert(certificates, key)
+        .map_err(|e| EchoError::TlsConfigFailed(e.to_string()))?;
+    Ok(Arc::new(config))
+}
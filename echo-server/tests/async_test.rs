@@ -0,0 +1,154 @@
+#![cfg(feature = "tokio")]
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use jdn_echo_server::{
+    AsyncEchoServer, ConsoleHandler, EchoError, EchoMode, EchoServerHandler, Framing,
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[tokio::test]
+async fn test_async_server() {
+    let mut server = AsyncEchoServer::new(
+        SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        Arc::new(ConsoleHandler),
+    );
+    assert_eq!(
+        server.local_addr(),
+        None,
+        "Not started - expected no address"
+    );
+
+    // Clean start
+    assert!(server.start().await.is_ok(), "Clean start failed");
+    assert!(server.is_running(), "Clean start failed - not running");
+    let server_address = server.local_addr().unwrap();
+    assert_ne!(
+        server_address.port(),
+        0,
+        "Clean start failed - port not assigned"
+    );
+    assert!(
+        matches!(server.start().await, Err(EchoError::AlreadyRunning)),
+        "Double start failed - expected AlreadyRunning"
+    );
+
+    // Broadcast
+    let mut client1 = TcpStream::connect(server_address).await.unwrap();
+    let mut client2 = TcpStream::connect(server_address).await.unwrap();
+    let frame = Framing::default().encode(b"hello").unwrap();
+    client1.write_all(&frame).await.unwrap();
+    assert_received(&mut client1, &frame, "Broadcast to sender").await;
+    assert_received(&mut client2, &frame, "Broadcast to other client").await;
+
+    // Sender only, where each client receives its own message and never the other's
+    server.set_mode(EchoMode::Sender);
+    let frame2 = Framing::default().encode(b"just client 2").unwrap();
+    client2.write_all(&frame2).await.unwrap();
+    assert_received(&mut client2, &frame2, "Echo to sender").await;
+    let frame1 = Framing::default().encode(b"just client 1").unwrap();
+    client1.write_all(&frame1).await.unwrap();
+    assert_received(&mut client1, &frame1, "Echo skipped other client").await;
+    let mut buf = [0; 1];
+    for (client, test_case) in [
+        (&mut client1, "Sender only to client 1"),
+        (&mut client2, "Sender only to client 2"),
+    ] {
+        assert!(
+            timeout(Duration::from_millis(100), client.read(&mut buf))
+                .await
+                .is_err(),
+            "{} failed - received another client's message",
+            test_case
+        );
+    }
+
+    // Graceful shutdown
+    server.shutdown().await;
+    assert!(!server.is_running(), "Shutdown failed - still running");
+    assert_eq!(server.local_addr(), None, "Shutdown failed - still bound");
+    let mut buf = [0; 1];
+    let read = timeout(READ_TIMEOUT, client1.read(&mut buf)).await;
+    assert!(
+        matches!(read, Ok(Ok(0))),
+        "Shutdown failed - client connection not closed"
+    );
+    assert!(
+        TcpStream::connect(server_address).await.is_err(),
+        "Shutdown failed - still accepting"
+    );
+}
+
+#[tokio::test]
+async fn test_async_server_stalled_reader() {
+    let handler = Arc::new(OverflowHandler::default());
+    let mut server = AsyncEchoServer::new(
+        SocketAddr::from_str("127.0.0.1:0").unwrap(),
+        handler.clone(),
+    );
+    server.set_mode(EchoMode::BroadcastExceptSender);
+    server.set_outbox_capacity(4);
+    server.start().await.unwrap();
+    let server_address = server.local_addr().unwrap();
+
+    let mut sender = TcpStream::connect(server_address).await.unwrap();
+    let mut reader = TcpStream::connect(server_address).await.unwrap();
+    let mut stalled = TcpStream::connect(server_address).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Far more is sent than the socket buffers and outbox of a client that never reads can hold,
+    // while the client that reads is not held up by it
+    for i in 0..400u16 {
+        let message = vec![b'a' + (i % 26) as u8; 64 * 1024];
+        let frame = Framing::default().encode(&message).unwrap();
+        sender.write_all(&frame).await.unwrap();
+        assert_received(&mut reader, &frame, "Stalled reader").await;
+    }
+
+    // The client that never read is disconnected once its outbox overflows
+    let mut received = Vec::new();
+    let read = timeout(READ_TIMEOUT, stalled.read_to_end(&mut received)).await;
+    assert!(
+        matches!(read, Ok(Ok(_))),
+        "Stalled reader failed - still connected"
+    );
+    assert!(
+        handler.overflowed.load(Ordering::Relaxed),
+        "Stalled reader failed - expected OutboxFull"
+    );
+    server.shutdown().await;
+}
+
+// A handler that records whether the outbox of a client overflowed.
+#[derive(Default)]
+struct OverflowHandler {
+    overflowed: AtomicBool,
+}
+
+impl EchoServerHandler for OverflowHandler {
+    fn on_error(&self, _peer: Option<SocketAddr>, error: &EchoError) {
+        if matches!(error, EchoError::OutboxFull) {
+            self.overflowed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+async fn assert_received(client: &mut TcpStream, expected: &[u8], test_case: &'static str) {
+    let mut buf = vec![0; expected.len()];
+    let read = timeout(READ_TIMEOUT, client.read_exact(&mut buf)).await;
+    assert!(
+        matches!(read, Ok(Ok(_))),
+        "{} failed - nothing received",
+        test_case
+    );
+    assert_eq!(buf, expected, "{} failed - wrong message", test_case);
+}
@@ -1,51 +1,886 @@
+use std::io::{Read, Write};
 use std::net::SocketAddr;
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream, UdpSocket};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use jdn_echo_server::EchoServer;
+use jdn_echo_core::memory;
+use jdn_echo_core::protocol::{self, DELAY_MS};
+use jdn_echo_core::transport::{Acceptor, Connector, Transport};
+use jdn_echo_server::{
+    ConsoleHandler, EchoError, EchoMode, EchoServer, EchoServerHandler, Endpoint, Framing,
+    ThreadModel,
+};
+
+const BIND_TIMEOUT: Duration = Duration::from_secs(2);
 
 #[test]
 fn test_server_lifecycle() {
-    let server_address = SocketAddr::from_str("127.0.0.1:8080").unwrap();
-    // Control - assert port can be bound
-    assert_port_available(server_address, "Control");
-    let mut server = EchoServer::new(server_address);
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
 
     // Stop without start
     server.stop();
-    assert_port_available(server_address, "Stop without start");
+    assert!(
+        matches!(server.local_addr(BIND_TIMEOUT), Err(EchoError::NotRunning)),
+        "Stop without start failed - expected NotRunning"
+    );
 
     // Clean start
-    server.start();
-    sleep_async_duration();
+    assert!(server.start().is_ok(), "Clean start failed");
+    let server_address = server.local_addr(BIND_TIMEOUT);
+    assert!(server_address.is_ok(), "Clean start failed - not bound");
+    let server_address = server_address.unwrap();
+    assert_ne!(
+        server_address.port(),
+        0,
+        "Clean start failed - port not assigned"
+    );
     assert_port_unavailable(server_address, "Clean start");
 
     // Double start
-    server.start();
-    sleep_async_duration();
+    assert!(
+        matches!(server.start(), Err(EchoError::AlreadyRunning)),
+        "Double start failed - expected AlreadyRunning"
+    );
     assert_port_unavailable(server_address, "Double start");
 
     // Clean stop
     server.stop();
-    sleep_async_duration();
     assert_port_available(server_address, "Clean stop");
+    assert!(
+        matches!(server.local_addr(BIND_TIMEOUT), Err(EchoError::NotRunning)),
+        "Clean stop failed - expected NotRunning"
+    );
 
     // Double stop
     server.stop();
-    sleep_async_duration();
     assert_port_available(server_address, "Double stop");
 
     // Start after external bind
     let test_server_result = TcpListener::bind(server_address);
     assert!(test_server_result.is_ok(), "External bind failed");
     let test_server = test_server_result.unwrap();
-    server.start();
+    server.set_address(server_address);
+    assert!(server.start().is_ok(), "Start after external bind failed");
     sleep_async_duration();
+    let bind_error = server.take_error();
+    assert!(
+        matches!(&bind_error, Some(EchoError::BindFailed(e)) if e.kind() == std::io::ErrorKind::AddrInUse),
+        "Start after external bind failed - expected AddrInUse bind error, got {:?}",
+        bind_error
+    );
+    assert!(
+        matches!(
+            server.local_addr(Duration::from_millis(0)),
+            Err(EchoError::TimedOut)
+        ),
+        "Start after external bind failed - expected TimedOut"
+    );
     std::mem::drop(test_server);
-    sleep_async_duration();
+    assert_eq!(
+        server.local_addr(BIND_TIMEOUT).ok(),
+        Some(server_address),
+        "Start after external bind failed - not bound"
+    );
     assert_port_unavailable(server_address, "Start after external bind");
+
+    // Drop while running
+    std::mem::drop(server);
+    assert_port_available(server_address, "Drop while running");
+}
+
+#[derive(Default)]
+struct BindErrorHandler {
+    bind_errors: AtomicUsize,
+}
+
+impl EchoServerHandler for BindErrorHandler {
+    fn on_error(&self, _peer: Option<SocketAddr>, error: &EchoError) {
+        if matches!(error, EchoError::BindFailed(_)) {
+            self.bind_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[test]
+fn test_server_bind_failure() {
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let server_address = test_server.local_addr().unwrap();
+    let handler = Arc::new(BindErrorHandler::default());
+    let mut server = EchoServer::new(server_address, Arc::clone(&handler) as _);
+    server.set_bind_backoff(Duration::from_millis(10), Duration::from_millis(10));
+
+    // Start succeeds while the address is in use, and every failed attempt is reported
+    assert!(server.start().is_ok(), "Bind failure failed - start failed");
+    sleep_async_duration();
+    assert!(server.is_running(), "Bind failure failed - not running");
+    assert!(
+        handler.bind_errors.load(Ordering::Relaxed) > 1,
+        "Bind failure failed - attempts not reported to handler"
+    );
+    assert!(
+        matches!(server.take_error(), Some(EchoError::BindFailed(e)) if e.kind() == std::io::ErrorKind::AddrInUse),
+        "Bind failure failed - expected AddrInUse bind error"
+    );
+    assert!(
+        matches!(
+            server.local_addr(Duration::from_millis(0)),
+            Err(EchoError::TimedOut)
+        ),
+        "Bind failure failed - expected TimedOut"
+    );
+
+    // The server binds once the address is released, and reports no further failures
+    std::mem::drop(test_server);
+    assert_eq!(
+        server.local_addr(BIND_TIMEOUT).ok(),
+        Some(server_address),
+        "Bind failure failed - not bound"
+    );
+    let reported = handler.bind_errors.load(Ordering::Relaxed);
+    sleep_async_duration();
+    assert_eq!(
+        handler.bind_errors.load(Ordering::Relaxed),
+        reported,
+        "Bind failure failed - reported after binding"
+    );
+    server.stop();
+}
+
+#[test]
+fn test_server_broadcast() {
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    let mut sender = connect_client(server_address);
+    let mut listener = connect_client(server_address);
+    sleep_async_duration();
+
+    // Every client receives each message, including the sender
+    for message in ["first", "second"] {
+        sender
+            .write_all(&Framing::default().encode(message.as_bytes()).unwrap())
+            .unwrap();
+        assert_received(&mut sender, message, "Broadcast to sender");
+        assert_received(&mut listener, message, "Broadcast to listener");
+    }
+
+    // Messages written together are still received separately
+    let mut frames = Framing::default().encode(b"third").unwrap();
+    frames.extend(Framing::default().encode(b"fourth").unwrap());
+    sender.write_all(&frames).unwrap();
+    for message in ["third", "fourth"] {
+        assert_received(&mut sender, message, "Combined write to sender");
+        assert_received(&mut listener, message, "Combined write to listener");
+    }
+
+    // Binary messages are echoed unchanged
+    let binary = [0, 159, 146, 150, 255];
+    sender
+        .write_all(&Framing::default().encode(&binary).unwrap())
+        .unwrap();
+    assert_received(&mut sender, binary, "Binary to sender");
+    assert_received(&mut listener, binary, "Binary to listener");
+
+    // Stopping the server disconnects all clients
+    server.stop();
+    let mut buf = [0; 16];
+    assert_eq!(
+        sender.read(&mut buf).unwrap(),
+        0,
+        "Stop failed - sender still connected"
+    );
+    assert_eq!(
+        listener.read(&mut buf).unwrap(),
+        0,
+        "Stop failed - listener still connected"
+    );
+}
+
+#[test]
+fn test_server_slow_reader() {
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(SilentHandler));
+    server.set_mode(EchoMode::BroadcastExceptSender);
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    let mut sender = connect_client(server_address);
+    let mut reader = connect_client(server_address);
+    sleep_async_duration();
+
+    // Far more is sent than the socket buffers can hold while the reader is not reading
+    let messages: Vec<Vec<u8>> = (0..200u8).map(|i| vec![b'a' + i % 26; 32 * 1024]).collect();
+    let sent = messages.clone();
+    let writer = thread::spawn(move || {
+        for message in sent {
+            sender
+                .write_all(&Framing::default().encode(&message).unwrap())
+                .unwrap();
+        }
+        sender
+    });
+    thread::sleep(Duration::from_secs(1));
+
+    // The reader's echoes are queued for it, so it still receives every message whole and in order
+    for message in messages {
+        assert_received(&mut reader, message, "Slow reader");
+    }
+    let _sender = writer.join().unwrap();
+    assert!(
+        server.take_error().is_none(),
+        "Slow reader failed - error reported"
+    );
+    server.stop();
+}
+
+#[test]
+fn test_server_stalled_reader() {
+    for thread_model in [ThreadModel::PerConnection, ThreadModel::Pool(1)] {
+        let mut server = EchoServer::new(ephemeral_address(), Arc::new(SilentHandler));
+        server.set_mode(EchoMode::BroadcastExceptSender);
+        server.set_thread_model(thread_model);
+        server.set_outbox_capacity(4);
+        server.start().unwrap();
+        let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+        let mut sender = connect_client(server_address);
+        let mut reader = connect_client(server_address);
+        let mut stalled = connect_client(server_address);
+        sleep_async_duration();
+
+        // Far more is sent than the socket buffers and outbox of a client that never reads can hold,
+        // while the client that reads is not held up by it
+        for i in 0..400u16 {
+            let message = vec![b'a' + (i % 26) as u8; 64 * 1024];
+            sender
+                .write_all(&Framing::default().encode(&message).unwrap())
+                .unwrap();
+            assert_received(&mut reader, message, "Stalled reader");
+        }
+
+        // New clients are still accepted and served
+        server.set_mode(EchoMode::Sender);
+        let mut client = connect_client(server_address);
+        send(&mut client, "hello");
+        assert_received(&mut client, "hello", "Client after stalled reader");
+
+        // The client that never read is disconnected once its outbox overflows
+        let mut received = Vec::new();
+        assert!(
+            stalled.read_to_end(&mut received).is_ok(),
+            "Stalled reader failed - still connected"
+        );
+        assert!(
+            matches!(server.take_error(), Some(EchoError::OutboxFull)),
+            "Stalled reader failed - expected OutboxFull"
+        );
+        server.stop();
+    }
+}
+
+#[test]
+fn test_server_frame_too_large() {
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    // A client announcing a frame beyond the limit is disconnected without the frame being buffered
+    let mut client = connect_client(server_address);
+    client.write_all(&u32::MAX.to_be_bytes()).unwrap();
+    let mut buf = [0; 1];
+    assert_eq!(
+        client.read(&mut buf).unwrap(),
+        0,
+        "Frame too large failed - still connected"
+    );
+    assert!(
+        matches!(server.take_error(), Some(EchoError::FrameTooLarge)),
+        "Frame too large failed - expected FrameTooLarge"
+    );
+
+    // Other clients are still served
+    let mut client = connect_client(server_address);
+    send(&mut client, "hello");
+    assert_received(&mut client, "hello", "After frame too large");
+    server.stop();
+}
+
+#[test]
+fn test_server_modes() {
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    let mut first = connect_client(server_address);
+    let mut second = connect_client(server_address);
+    sleep_async_duration();
+
+    // Each message is followed by one that every client expects, so an unexpected echo
+    // is detected as a mismatch instead of waiting for a timeout.
+    server.set_mode(EchoMode::Sender);
+    assert_eq!(server.mode(), EchoMode::Sender);
+    send(&mut first, "sender");
+    assert_received(&mut first, "sender", "Sender mode");
+
+    server.set_mode(EchoMode::BroadcastExceptSender);
+    send(&mut second, "except second");
+    assert_received(&mut first, "except second", "Broadcast except sender mode");
+    send(&mut first, "except first");
+    assert_received(&mut second, "except first", "Broadcast except sender mode");
+
+    server.set_mode(EchoMode::Broadcast);
+    send(&mut first, "broadcast");
+    assert_received(&mut first, "broadcast", "Broadcast mode to sender");
+    assert_received(&mut second, "broadcast", "Broadcast mode to listener");
+
+    // Modes are parsed from their displayed names
+    for mode in [
+        EchoMode::Sender,
+        EchoMode::Broadcast,
+        EchoMode::BroadcastExceptSender,
+    ] {
+        assert_eq!(EchoMode::from_str(&mode.to_string()), Ok(mode));
+    }
+    assert!(EchoMode::from_str("everyone").is_err());
+    server.stop();
+}
+
+#[test]
+fn test_server_rfc862() {
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.set_rfc862(true);
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    // TCP bytes are echoed verbatim to the sender only, without framing or UTF-8 decoding
+    let mut first = connect_client(server_address);
+    let mut second = connect_client(server_address);
+    sleep_async_duration();
+    let data = [0, 255, b'\n', 1];
+    first.write_all(&data).unwrap();
+    second.write_all(b"second").unwrap();
+    let mut buf = [0; 6];
+    second.read_exact(&mut buf).unwrap();
+    assert_eq!(
+        &buf, b"second",
+        "TCP echo failed - expected only the sender's bytes"
+    );
+    let mut buf = [0; 4];
+    first.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, data, "TCP echo failed");
+
+    // UDP datagrams on the same port are echoed back to their source
+    let udp_client = UdpSocket::bind(ephemeral_address()).unwrap();
+    udp_client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    for datagram in [&b"datagram"[..], &data[..]] {
+        udp_client.send_to(datagram, server_address).unwrap();
+        let mut buf = [0; 64];
+        let (len, source) = udp_client.recv_from(&mut buf).unwrap();
+        assert_eq!(&buf[..len], datagram, "UDP echo failed");
+        assert_eq!(source, server_address, "UDP echo failed - wrong source");
+    }
+
+    // Stopping the server releases the UDP port
+    server.stop();
+    assert!(
+        UdpSocket::bind(server_address).is_ok(),
+        "Stop failed - UDP port still bound"
+    );
+}
+
+#[test]
+fn test_server_thread_models() {
+    for thread_model in [
+        ThreadModel::PerConnection,
+        ThreadModel::Pool(1),
+        ThreadModel::Pool(4),
+    ] {
+        let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+        server.set_thread_model(thread_model);
+        server.set_mode(EchoMode::Sender);
+        server.start().unwrap();
+        let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+        // Every client is served, whichever thread it is assigned to
+        let mut clients: Vec<TcpStream> = (0..20).map(|_| connect_client(server_address)).collect();
+        sleep_async_duration();
+        for (index, client) in clients.iter_mut().enumerate() {
+            send(client, &format!("client {}", index));
+        }
+        for (index, client) in clients.iter_mut().enumerate() {
+            assert_received(client, format!("client {}", index), "Thread model echo");
+        }
+
+        // Stopping the server disconnects every client
+        server.stop();
+        let mut buf = [0; 16];
+        for client in clients.iter_mut() {
+            assert_eq!(
+                client.read(&mut buf).unwrap(),
+                0,
+                "{:?} stop failed - client still connected",
+                thread_model
+            );
+        }
+    }
+}
+
+struct SilentHandler;
+
+impl EchoServerHandler for SilentHandler {}
+
+#[derive(Default)]
+struct RecordingHandler {
+    events: Mutex<Vec<String>>,
+}
+
+impl EchoServerHandler for RecordingHandler {
+    fn on_connect(&self, _peer: SocketAddr) {
+        self.events.lock().unwrap().push(String::from("connect"));
+    }
+
+    fn on_message(&self, _peer: SocketAddr, message: String) -> Option<String> {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("message {}", message));
+        if message == "drop" {
+            None
+        } else {
+            Some(message.to_uppercase())
+        }
+    }
+
+    fn on_disconnect(&self, _peer: SocketAddr) {
+        self.events.lock().unwrap().push(String::from("disconnect"));
+    }
+}
+
+#[test]
+fn test_server_handler() {
+    let handler = Arc::new(RecordingHandler::default());
+    let mut server = EchoServer::new(ephemeral_address(), Arc::clone(&handler) as _);
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    let mut client = connect_client(server_address);
+    sleep_async_duration();
+    let mut frames = Framing::default().encode(b"drop").unwrap();
+    frames.extend(Framing::default().encode(b"keep").unwrap());
+    client.write_all(&frames).unwrap();
+
+    // Dropped messages are not echoed, and kept messages are transformed
+    assert_received(&mut client, "KEEP", "Transform message");
+
+    // Tagged messages reach the handler without their tag, which is added back to the reply
+    client
+        .write_all(
+            &Framing::default()
+                .encode(&protocol::tag(42, b"tagged"))
+                .unwrap(),
+        )
+        .unwrap();
+    assert_received(&mut client, protocol::tag(42, b"TAGGED"), "Tagged message");
+    std::mem::drop(client);
+    sleep_async_duration();
+    server.stop();
+
+    assert_eq!(
+        *handler.events.lock().unwrap(),
+        vec![
+            "connect",
+            "message drop",
+            "message keep",
+            "message tagged",
+            "disconnect"
+        ]
+    );
+}
+
+#[test]
+fn test_server_tokens() {
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.set_tokens(vec!["secret"]);
+    server.set_auth_timeout(Duration::from_secs(1));
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    // Messages sent along with an accepted token are echoed
+    let mut trusted = connect_client(server_address);
+    let mut frames = Framing::default()
+        .encode(&protocol::auth_request("secret"))
+        .unwrap();
+    frames.extend(Framing::default().encode(b"first").unwrap());
+    trusted.write_all(&frames).unwrap();
+    assert_received(&mut trusted, protocol::ACCEPTED, "Accepted token");
+    assert_received(&mut trusted, "first", "Accepted token");
+
+    // Clients that have not presented a token do not receive broadcasts
+    let mut waiting = connect_client(server_address);
+    waiting
+        .set_read_timeout(Some(Duration::from_millis(250)))
+        .unwrap();
+    sleep_async_duration();
+    send(&mut trusted, "second");
+    assert_received(&mut trusted, "second", "Broadcast while waiting");
+    let mut buf = [0; 1];
+    assert!(
+        waiting.read(&mut buf).is_err(),
+        "Broadcast while waiting failed - unauthenticated client echoed"
+    );
+
+    // Clients without an accepted token are told why, and disconnected
+    for (first_message, reason, test_case) in [
+        (b"hello".to_vec(), "token required", "Missing token"),
+        (
+            protocol::auth_request("guess"),
+            "token not accepted",
+            "Wrong token",
+        ),
+        (
+            protocol::auth_request(&"x".repeat(2048)),
+            "token too long",
+            "Long token",
+        ),
+    ] {
+        let mut intruder = connect_client(server_address);
+        intruder
+            .write_all(&Framing::default().encode(&first_message).unwrap())
+            .unwrap();
+        assert_received(&mut intruder, protocol::denial(reason), test_case);
+        assert_eq!(
+            intruder.read(&mut buf).unwrap(),
+            0,
+            "{} failed - still connected",
+            test_case
+        );
+        assert!(
+            matches!(
+                server.take_error(),
+                Some(EchoError::AuthenticationFailed(_))
+            ),
+            "{} failed - expected AuthenticationFailed",
+            test_case
+        );
+    }
+
+    // Clients are disconnected without waiting for the rest of a first message that is too long, or never sent
+    let mut partial = Framing::default()
+        .encode(&protocol::auth_request(&"x".repeat(2048)))
+        .unwrap();
+    partial.truncate(1536);
+    for (first_message, reason, test_case) in [
+        (partial, "token too long", "Partial long token"),
+        (Vec::new(), "token not presented in time", "No token"),
+    ] {
+        let mut intruder = connect_client(server_address);
+        intruder
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        intruder.write_all(&first_message).unwrap();
+        assert_received(&mut intruder, protocol::denial(reason), test_case);
+        assert_eq!(
+            intruder.read(&mut buf).unwrap(),
+            0,
+            "{} failed - still connected",
+            test_case
+        );
+    }
+    server.stop();
+
+    // Tokens loaded from a file, one per line
+    let token_path = std::env::temp_dir().join(format!("jdn-echo-tokens-{}", std::process::id()));
+    assert!(
+        matches!(server.load_tokens(&token_path), Err(EchoError::Io(_))),
+        "Missing token file failed - expected Io"
+    );
+    std::fs::write(&token_path, "first\n\n  second  \n").unwrap();
+    assert!(server.load_tokens(&token_path).is_ok(), "Token file failed");
+    std::fs::remove_file(&token_path).unwrap();
+    server.set_max_auth_size(protocol::auth_request("second").len());
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+    let mut intruder = connect_client(server_address);
+    intruder
+        .write_all(
+            &Framing::default()
+                .encode(&protocol::auth_request("second-guess"))
+                .unwrap(),
+        )
+        .unwrap();
+    assert_received(
+        &mut intruder,
+        protocol::denial("token too long"),
+        "Smaller token limit",
+    );
+    let mut trusted = connect_client(server_address);
+    trusted
+        .write_all(
+            &Framing::default()
+                .encode(&protocol::auth_request("second"))
+                .unwrap(),
+        )
+        .unwrap();
+    send(&mut trusted, "from file");
+    assert_received(&mut trusted, protocol::ACCEPTED, "Token from file");
+    assert_received(&mut trusted, "from file", "Token from file");
+    server.stop();
+
+    // Clients are served without a token once the tokens are cleared
+    server.clear_tokens();
+    server.start().unwrap();
+    let mut client = connect_client(server.local_addr(BIND_TIMEOUT).unwrap());
+    send(&mut client, "open");
+    assert_received(&mut client, "open", "Cleared tokens");
+    server.stop();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_server_unix_socket() {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let socket_path =
+        std::env::temp_dir().join(format!("jdn-echo-server-{}.sock", std::process::id()));
+    // A socket file left behind by a server that did not stop cleanly
+    std::mem::drop(UnixListener::bind(&socket_path).unwrap());
+    assert!(socket_path.exists());
+
+    let mut server = EchoServer::new(
+        Endpoint::Unix(socket_path.clone()),
+        Arc::new(ConsoleHandler),
+    );
+    server.start().unwrap();
+    assert_eq!(
+        server.local_endpoint(BIND_TIMEOUT).unwrap(),
+        Endpoint::Unix(socket_path.clone()),
+        "Stale socket failed"
+    );
+    assert!(
+        server.local_addr(BIND_TIMEOUT).is_err(),
+        "Local address failed - Unix domain socket has an IP address"
+    );
+
+    // The socket of a running server is not mistaken for a stale one
+    let mut other = EchoServer::new(Endpoint::Unix(socket_path.clone()), Arc::new(SilentHandler));
+    other.start().unwrap();
+    sleep_async_duration();
+    assert!(
+        matches!(other.take_error(), Some(EchoError::BindFailed(_))),
+        "Live socket failed - expected BindFailed"
+    );
+    other.stop();
+    assert!(
+        socket_path.exists(),
+        "Live socket failed - socket file removed"
+    );
+
+    // Messages are broadcast between clients of the socket
+    let mut first = UnixStream::connect(&socket_path).unwrap();
+    first
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut second = UnixStream::connect(&socket_path).unwrap();
+    second
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    sleep_async_duration();
+    first
+        .write_all(&Framing::default().encode(b"hello").unwrap())
+        .unwrap();
+    assert_received(&mut first, "hello", "Unix sender");
+    assert_received(&mut second, "hello", "Unix broadcast");
+
+    // The socket file is removed when the server stops
+    server.stop();
+    assert!(!socket_path.exists(), "Stop failed - socket file remains");
+    let mut buf = [0; 1];
+    assert_eq!(
+        first.read(&mut buf).unwrap(),
+        0,
+        "Stop failed - still connected"
+    );
+}
+
+#[test]
+fn test_server_acceptor() {
+    let (connector, acceptor) = memory::channel();
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.set_acceptor(Some(Arc::new(acceptor)));
+    server.start().unwrap();
+    assert!(
+        server.local_endpoint(BIND_TIMEOUT).is_err(),
+        "Local endpoint failed - memory transport has an endpoint"
+    );
+
+    // Messages are echoed over the in-memory transport
+    let mut client = connector.connect(Duration::ZERO).unwrap();
+    send(&mut client, "hello");
+    assert_received(&mut client, "hello", "Memory echo");
+
+    // Clients are disconnected when the server stops
+    server.stop();
+    let mut buf = [0; 1];
+    assert_eq!(
+        client.read(&mut buf).unwrap(),
+        0,
+        "Stop failed - still connected"
+    );
+}
+
+#[test]
+fn test_server_placeholder_address_reuse() {
+    let (connector, acceptor) = memory::channel();
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(SilentHandler));
+    server.set_acceptor(Some(Arc::new(acceptor)));
+    server.set_thread_model(ThreadModel::Pool(1));
+    server.set_outbox_capacity(1);
+    server.set_mode(EchoMode::Sender);
+    server.start().unwrap();
+
+    // Enough clients connect and leave after the first for its placeholder address to be given to the second
+    let mut first = connector.connect(Duration::ZERO).unwrap();
+    for _ in 0..u16::MAX - 1 {
+        std::mem::drop(connector.connect(Duration::ZERO).unwrap());
+    }
+    let mut second = connector.connect(Duration::ZERO).unwrap();
+    send(&mut second, "second");
+    assert_received(&mut second, "second", "Reused address");
+
+    // Each client still receives only its own echoes
+    send(&mut first, "first");
+    send(&mut second, "again");
+    assert_received(&mut second, "again", "Reused address to second client");
+    assert_received(&mut first, "first", "Reused address to first client");
+
+    // The first client is still served once the second disconnects
+    std::mem::drop(second);
+    sleep_async_duration();
+    send(&mut first, "still here");
+    assert_received(&mut first, "still here", "Reused address after disconnect");
+    server.stop();
+}
+
+// An acceptor that fails with each of the given errors in turn, then accepts clients of an in-memory transport.
+struct FailingAcceptor {
+    errors: Mutex<Vec<std::io::Error>>,
+    acceptor: memory::MemoryAcceptor,
+}
+
+impl Acceptor for FailingAcceptor {
+    fn accept(&self) -> std::io::Result<(Box<dyn Transport>, SocketAddr)> {
+        match self.errors.lock().unwrap().pop() {
+            Some(e) => Err(e),
+            None => self.acceptor.accept(),
+        }
+    }
+
+    fn local_endpoint(&self) -> Option<Endpoint> {
+        None
+    }
+}
+
+#[test]
+fn test_server_accept_failure() {
+    use std::io::{Error, ErrorKind};
+
+    // Errors that belong to one connection, or to a shortage of resources, are reported and accepting continues
+    let (connector, acceptor) = memory::channel();
+    let transient = vec![
+        Error::other("too many open files"),
+        Error::from(ErrorKind::Interrupted),
+        Error::from(ErrorKind::ConnectionAborted),
+    ];
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.set_bind_backoff(Duration::from_millis(10), Duration::from_millis(10));
+    server.set_acceptor(Some(Arc::new(FailingAcceptor {
+        errors: Mutex::new(transient),
+        acceptor,
+    })));
+    server.start().unwrap();
+    let mut client = connector.connect(Duration::ZERO).unwrap();
+    send(&mut client, "hello");
+    assert_received(&mut client, "hello", "Transient accept failure");
+    assert!(
+        server.is_running(),
+        "Transient accept failure failed - stopped"
+    );
+    assert!(
+        matches!(server.take_error(), Some(EchoError::Io(e)) if e.kind() == ErrorKind::Other),
+        "Transient accept failure failed - expected Io"
+    );
+    server.stop();
+
+    // An error that leaves the acceptor unusable stops the server, rather than leaving it running without
+    // accepting clients
+    let (_connector, acceptor) = memory::channel();
+    server.set_acceptor(Some(Arc::new(FailingAcceptor {
+        errors: Mutex::new(vec![Error::from(ErrorKind::InvalidInput)]),
+        acceptor,
+    })));
+    server.start().unwrap();
+    let deadline = Instant::now() + BIND_TIMEOUT;
+    while server.is_running() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(
+        !server.is_running(),
+        "Fatal accept failure failed - still running"
+    );
+    assert!(
+        matches!(server.take_error(), Some(EchoError::Io(e)) if e.kind() == ErrorKind::InvalidInput),
+        "Fatal accept failure failed - expected Io"
+    );
+
+    // The server can be started again without being stopped first
+    server.set_acceptor(None);
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT);
+    assert!(
+        server_address.is_ok(),
+        "Start after accept failure failed - not bound"
+    );
+    thread::sleep(Duration::from_millis(DELAY_MS));
+    let mut client = connect_client(server_address.unwrap());
+    send(&mut client, "restarted");
+    assert_received(&mut client, "restarted", "Start after accept failure");
+    assert!(
+        server.local_addr(Duration::ZERO).is_ok(),
+        "Start after accept failure failed - acceptor released"
+    );
+    server.stop();
+}
+
+fn ephemeral_address() -> SocketAddr {
+    SocketAddr::from_str("127.0.0.1:0").unwrap()
+}
+
+fn connect_client(server_address: SocketAddr) -> TcpStream {
+    let client = TcpStream::connect(server_address).unwrap();
+    client
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    client
+}
+
+fn send(client: &mut impl Write, message: &str) {
+    client
+        .write_all(&Framing::default().encode(message.as_bytes()).unwrap())
+        .unwrap();
+}
+
+fn assert_received(client: &mut impl Read, expected: impl AsRef<[u8]>, test_case: &'static str) {
+    let expected = Framing::default().encode(expected.as_ref()).unwrap();
+    let mut buf = vec![0; expected.len()];
+    let result = client.read_exact(&mut buf);
+    assert!(result.is_ok(), "{} failed: {:?}", test_case, result);
+    assert_eq!(buf, expected, "{} failed", test_case);
 }
 
 fn assert_port_available(server_address: SocketAddr, test_case: &'static str) {
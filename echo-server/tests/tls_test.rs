@@ -0,0 +1,393 @@
+#![cfg(feature = "tls")]
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use jdn_echo_core::memory;
+use jdn_echo_core::transport::Connector;
+use jdn_echo_server::{
+    ConsoleHandler, EchoError, EchoServer, EchoServerHandler, Endpoint, Framing,
+};
+
+const BIND_TIMEOUT: Duration = Duration::from_secs(2);
+
+type TlsClient<S = TcpStream> = StreamOwned<ClientConnection, S>;
+
+#[test]
+fn test_server_tls() {
+    let dir = std::env::temp_dir().join(format!("jdn-echo-server-tls-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (ca, ca_key) = generate_ca();
+    let (server_cert, server_key) = generate_signed(&ca, &ca_key, |params| {
+        params.subject_alt_names = vec![rcgen::SanType::IpAddress([127, 0, 0, 1].into())];
+    });
+    let cert_path = dir.join("server.pem");
+    let key_path = dir.join("server.key");
+    fs::write(&cert_path, server_cert.pem()).unwrap();
+    fs::write(&key_path, server_key.serialize_pem()).unwrap();
+
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+
+    // Invalid certificate files
+    let missing_path = dir.join("missing.pem");
+    assert!(
+        matches!(
+            server.set_tls_certificate(&missing_path, &key_path),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Missing certificate failed - expected TlsConfigFailed"
+    );
+    assert!(
+        matches!(
+            server.set_tls_certificate(&key_path, &key_path),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Key as certificate failed - expected TlsConfigFailed"
+    );
+    assert!(
+        matches!(
+            server.set_tls_certificate(&cert_path, &cert_path),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Certificate as key failed - expected TlsConfigFailed"
+    );
+
+    // Broadcast between TLS clients
+    assert!(
+        server.set_tls_certificate(&cert_path, &key_path).is_ok(),
+        "Valid certificate failed"
+    );
+    assert!(server.start().is_ok(), "TLS start failed");
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+    let mut sender = connect_tls_client(server_address, client_config(ca.der(), None)).unwrap();
+    let mut listener = connect_tls_client(server_address, client_config(ca.der(), None)).unwrap();
+    sender
+        .write_all(&Framing::default().encode(b"secret").unwrap())
+        .unwrap();
+    assert_received(&mut sender, b"secret", "Broadcast to TLS sender");
+    assert_received(&mut listener, b"secret", "Broadcast to TLS listener");
+
+    // Plaintext clients are not echoed
+    let mut plain = TcpStream::connect(server_address).unwrap();
+    plain.set_read_timeout(Some(BIND_TIMEOUT)).unwrap();
+    plain
+        .write_all(&Framing::default().encode(b"plain").unwrap())
+        .unwrap();
+    let mut received = Vec::new();
+    let _ = plain.read_to_end(&mut received);
+    assert!(
+        !received.ends_with(b"plain"),
+        "Plaintext client failed - message echoed"
+    );
+
+    // Stopping the server ends the TLS sessions cleanly
+    server.stop();
+    let mut buf = [0; 1];
+    assert!(
+        matches!(sender.read(&mut buf), Ok(0)),
+        "Stop failed - TLS session not closed"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_server_mutual_tls() {
+    let dir = std::env::temp_dir().join(format!("jdn-echo-server-mtls-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (ca, ca_key) = generate_ca();
+    let (server_cert, server_key) = generate_signed(&ca, &ca_key, |params| {
+        params.subject_alt_names = vec![rcgen::SanType::IpAddress([127, 0, 0, 1].into())];
+    });
+    let (client_cert, client_key) = generate_signed(&ca, &ca_key, |params| {
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "echo-client");
+    });
+    let (other_ca, other_ca_key) = generate_ca();
+    let (other_cert, other_key) = generate_signed(&other_ca, &other_ca_key, |params| {
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "intruder");
+    });
+    let ca_path = dir.join("ca.pem");
+    let cert_path = dir.join("server.pem");
+    let key_path = dir.join("server.key");
+    fs::write(&ca_path, ca.pem()).unwrap();
+    fs::write(&cert_path, server_cert.pem()).unwrap();
+    fs::write(&key_path, server_key.serialize_pem()).unwrap();
+
+    let handler = Arc::new(SubjectHandler::default());
+    let mut server = EchoServer::new(ephemeral_address(), handler.clone());
+
+    // Invalid CA file
+    assert!(
+        matches!(
+            server.set_tls_client_ca(&dir.join("missing.pem")),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Missing client CA failed - expected TlsConfigFailed"
+    );
+
+    // The client CA may be set before the server certificate
+    assert!(
+        server.set_tls_client_ca(&ca_path).is_ok(),
+        "Valid client CA failed"
+    );
+    assert!(
+        server.set_tls_certificate(&cert_path, &key_path).is_ok(),
+        "Valid certificate failed"
+    );
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+
+    // Clients with a certificate issued by the client CA are echoed, and their subject is reported
+    let identity = (client_cert.der().clone(), client_key);
+    let mut client =
+        connect_tls_client(server_address, client_config(ca.der(), Some(&identity))).unwrap();
+    client
+        .write_all(&Framing::default().encode(b"trusted").unwrap())
+        .unwrap();
+    assert_received(&mut client, b"trusted", "Trusted client");
+    assert_eq!(
+        *handler.subjects.lock().unwrap(),
+        vec![String::from("CN=echo-client")],
+        "Trusted client failed - subject not reported"
+    );
+
+    // Clients without a certificate, or with one from another CA, are rejected and never echoed
+    let other_identity = (other_cert.der().clone(), other_key);
+    for (identity, test_case) in [
+        (None, "Client without certificate"),
+        (Some(&other_identity), "Client with untrusted certificate"),
+    ] {
+        let result = connect_tls_client(server_address, client_config(ca.der(), identity))
+            .and_then(|mut intruder| {
+                intruder.write_all(&Framing::default().encode(b"intruder").unwrap())?;
+                let mut received = Vec::new();
+                intruder.read_to_end(&mut received)?;
+                Ok(received)
+            });
+        assert!(
+            result.is_err() || result.as_ref().unwrap().is_empty(),
+            "{} failed - message echoed",
+            test_case
+        );
+    }
+    client
+        .write_all(&Framing::default().encode(b"still trusted").unwrap())
+        .unwrap();
+    assert_received(
+        &mut client,
+        b"still trusted",
+        "Trusted client after rejections",
+    );
+    assert_eq!(
+        handler.subjects.lock().unwrap().len(),
+        1,
+        "Rejected clients failed - subject reported"
+    );
+
+    // A client is identified as soon as its handshake completes, even if it never sends a message
+    let _silent =
+        connect_tls_client(server_address, client_config(ca.der(), Some(&identity))).unwrap();
+    let deadline = Instant::now() + BIND_TIMEOUT;
+    while handler.subjects.lock().unwrap().len() < 2 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(
+        *handler.subjects.lock().unwrap(),
+        vec![String::from("CN=echo-client"); 2],
+        "Silent client failed - subject not reported"
+    );
+
+    // Once the client CA is cleared, a restarted server echoes clients without a certificate
+    server.stop();
+    assert!(
+        server.clear_tls_client_ca().is_ok(),
+        "Clear client CA failed"
+    );
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+    let mut anonymous = connect_tls_client(server_address, client_config(ca.der(), None)).unwrap();
+    anonymous
+        .write_all(&Framing::default().encode(b"anonymous").unwrap())
+        .unwrap();
+    assert_received(&mut anonymous, b"anonymous", "Client CA cleared");
+
+    // Once the certificate is cleared, a restarted server echoes plaintext clients
+    server.stop();
+    server.clear_tls_certificate();
+    server.start().unwrap();
+    let server_address = server.local_addr(BIND_TIMEOUT).unwrap();
+    let mut plain = TcpStream::connect(server_address).unwrap();
+    plain.set_read_timeout(Some(BIND_TIMEOUT)).unwrap();
+    let expected = Framing::default().encode(b"plain").unwrap();
+    plain.write_all(&expected).unwrap();
+    let mut received = vec![0; expected.len()];
+    let result = plain.read_exact(&mut received);
+    assert!(
+        result.is_ok() && received == expected,
+        "Certificate cleared failed - plaintext client not echoed: {:?}",
+        result
+    );
+
+    server.stop();
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_server_tls_streams() {
+    let dir = std::env::temp_dir().join(format!(
+        "jdn-echo-server-tls-streams-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let (ca, ca_key) = generate_ca();
+    let (server_cert, server_key) = generate_signed(&ca, &ca_key, |params| {
+        params.subject_alt_names = vec![rcgen::SanType::DnsName(
+            rcgen::Ia5String::try_from("localhost").unwrap(),
+        )];
+    });
+    let cert_path = dir.join("server.pem");
+    let key_path = dir.join("server.key");
+    fs::write(&cert_path, server_cert.pem()).unwrap();
+    fs::write(&key_path, server_key.serialize_pem()).unwrap();
+    let server_name = ServerName::try_from("localhost").unwrap();
+
+    let (connector, acceptor) = memory::channel();
+    let mut server = EchoServer::new(ephemeral_address(), Arc::new(ConsoleHandler));
+    server.set_tls_certificate(&cert_path, &key_path).unwrap();
+
+    // Clients of an acceptor are served over TLS
+    server.set_acceptor(Some(Arc::new(acceptor)));
+    server.start().unwrap();
+    let socket = connector.connect(Duration::ZERO).unwrap();
+    let mut client =
+        open_tls_client(socket, server_name.clone(), client_config(ca.der(), None)).unwrap();
+    client
+        .write_all(&Framing::default().encode(b"memory").unwrap())
+        .unwrap();
+    assert_received(&mut client, b"memory", "Acceptor over TLS");
+    server.stop();
+
+    // Clients of a Unix domain socket are served over TLS
+    #[cfg(unix)]
+    {
+        let socket_path = dir.join("server.sock");
+        server.set_acceptor(None);
+        server.set_address(Endpoint::Unix(socket_path.clone()));
+        server.start().unwrap();
+        server.local_endpoint(BIND_TIMEOUT).unwrap();
+        let socket = std::os::unix::net::UnixStream::connect(&socket_path).unwrap();
+        socket.set_read_timeout(Some(BIND_TIMEOUT)).unwrap();
+        let mut client =
+            open_tls_client(socket, server_name, client_config(ca.der(), None)).unwrap();
+        client
+            .write_all(&Framing::default().encode(b"unix").unwrap())
+            .unwrap();
+        assert_received(&mut client, b"unix", "Unix domain socket over TLS");
+        server.stop();
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[derive(Default)]
+struct SubjectHandler {
+    subjects: Mutex<Vec<String>>,
+}
+
+impl EchoServerHandler for SubjectHandler {
+    fn on_authenticate(&self, _peer: SocketAddr, subject: &str) {
+        self.subjects.lock().unwrap().push(subject.to_string());
+    }
+}
+
+fn ephemeral_address() -> SocketAddr {
+    SocketAddr::from_str("127.0.0.1:0").unwrap()
+}
+
+fn generate_ca() -> (Certificate, KeyPair) {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    (params.self_signed(&key).unwrap(), key)
+}
+
+fn generate_signed(
+    ca: &Certificate,
+    ca_key: &KeyPair,
+    customize: impl FnOnce(&mut CertificateParams),
+) -> (Certificate, KeyPair) {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::new()).unwrap();
+    customize(&mut params);
+    (params.signed_by(&key, ca, ca_key).unwrap(), key)
+}
+
+fn client_config(
+    ca: &CertificateDer<'static>,
+    identity: Option<&(CertificateDer<'static>, KeyPair)>,
+) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.clone()).unwrap();
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+    match identity {
+        Some((cert, key)) => {
+            let key = PrivateKeyDer::from_pem_slice(key.serialize_pem().as_bytes()).unwrap();
+            builder
+                .with_client_auth_cert(vec![cert.clone()], key)
+                .unwrap()
+        }
+        None => builder.with_no_client_auth(),
+    }
+}
+
+fn connect_tls_client(server_address: SocketAddr, config: ClientConfig) -> io::Result<TlsClient> {
+    let server_name = ServerName::IpAddress(server_address.ip().into());
+    let socket = TcpStream::connect(server_address)?;
+    socket.set_read_timeout(Some(BIND_TIMEOUT))?;
+    open_tls_client(socket, server_name, config)
+}
+
+fn open_tls_client<S: Read + Write>(
+    socket: S,
+    server_name: ServerName<'static>,
+    config: ClientConfig,
+) -> io::Result<TlsClient<S>> {
+    let session = ClientConnection::new(Arc::new(config), server_name).unwrap();
+    let mut client = StreamOwned::new(session, socket);
+    // Complete the handshake so the server has accepted the client before any message is sent
+    while client.conn.is_handshaking() {
+        client.conn.complete_io(&mut client.sock)?;
+    }
+    Ok(client)
+}
+
+fn assert_received<S: Read + Write>(
+    client: &mut TlsClient<S>,
+    expected: &[u8],
+    test_case: &'static str,
+) {
+    let expected = Framing::default().encode(expected).unwrap();
+    let mut buf = vec![0; expected.len()];
+    let result = client.read_exact(&mut buf);
+    assert!(result.is_ok(), "{} failed: {:?}", test_case, result);
+    assert_eq!(buf, expected, "{} failed", test_case);
+}
@@ -0,0 +1,181 @@
+//! An asynchronous echo client, built on tokio. Available with the `tokio` feature.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::framing::Framing;
+use jdn_echo_core::protocol::{Message, BUFFER_SIZE};
+
+/// A TCP client that sends and receives text or bytes to and from an echo server on a tokio runtime.
+/// Unlike EchoClient, it connects once: messages cannot be sent while disconnected, and it does not reconnect.
+pub struct AsyncEchoClient {
+    // The address to which the client connects.
+    address: SocketAddr,
+    // The framing used to delimit messages sent to and received from the server.
+    framing: Framing,
+    // The Senders used to deliver messages received from the server to each subscriber.
+    subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<Message>>>>,
+    // The half of the stream used to send messages, while connected. The read task clears it once the connection ends.
+    writer: Arc<tokio::sync::Mutex<Option<OwnedWriteHalf>>>,
+    // The Sender used to stop the read task. Dropping it also stops the task.
+    shutdown: Option<watch::Sender<bool>>,
+    // The task that reads messages from the server, awaited on shutdown.
+    read_task: Option<JoinHandle<Result<(), EchoError>>>,
+}
+
+impl AsyncEchoClient {
+    /// Constructs a new AsyncEchoClient with the given address.
+    pub fn new(address: SocketAddr) -> Self {
+        AsyncEchoClient {
+            address,
+            framing: Framing::default(),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            writer: Arc::new(tokio::sync::Mutex::new(None)),
+            shutdown: None,
+            read_task: None,
+        }
+    }
+
+    /// Sets the framing used to delimit messages. The change will have no effect until the next call to connect.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing
+    }
+
+    /// Gets the flag that indicates if the client is connected to the server.
+    pub fn is_connected(&self) -> bool {
+        self.read_task
+            .as_ref()
+            .is_some_and(|task| !task.is_finished())
+    }
+
+    /// Connects to the server, and starts reading the messages it sends. Must be called from a tokio runtime.
+    /// Returns an error if the client is already connected, or if the connection could not be made.
+    pub async fn connect(&mut self) -> Result<(), EchoError> {
+        if self.is_connected() {
+            return Err(EchoError::AlreadyRunning);
+        }
+        let stream = TcpStream::connect(self.address)
+            .await
+            .map_err(EchoError::ConnectFailed)?;
+        let peer = stream.peer_addr()?;
+        let (reader, writer) = stream.into_split();
+        let (shutdown_sender, shutdown_receiver) = watch::channel(false);
+        *self.writer.lock().await = Some(writer);
+        self.read_task = Some(tokio::spawn(read_process(
+            reader,
+            peer,
+            self.framing,
+            Arc::clone(&self.subscribers),
+            Arc::clone(&self.writer),
+            shutdown_receiver,
+        )));
+        self.shutdown = Some(shutdown_sender);
+        Ok(())
+    }
+
+    /// Sends the given text or bytes to the server as a single message, waiting until it has been written.
+    /// Returns NotRunning if the client is not connected, including once the server has closed the connection,
+    /// or FrameTooLarge if the message cannot be framed.
+    pub async fn send(&self, message: impl AsRef<[u8]>) -> Result<(), EchoError> {
+        let mut writer = self.writer.lock().await;
+        let writer = writer.as_mut().ok_or(EchoError::NotRunning)?;
+        let data = self.framing.encode(message.as_ref())?;
+        writer.write_all(&data).await?;
+        Ok(())
+    }
+
+    /// Subscribes to the messages received from the server. Every message received after this call
+    /// is delivered to the returned MessageStream, which ends when the client disconnects.
+    pub fn messages(&self) -> MessageStream {
+        let (message_sender, message_receiver) = mpsc::unbounded_channel();
+        self.subscribers.lock().unwrap().push(message_sender);
+        MessageStream {
+            receiver: message_receiver,
+        }
+    }
+
+    /// Closes the connection to the server, waiting for the read task to exit.
+    /// Returns the error that ended the connection, if the server did not close it cleanly.
+    pub async fn shutdown(&mut self) -> Result<(), EchoError> {
+        if let Some(mut writer) = self.writer.lock().await.take() {
+            // Let the server see the end of the stream rather than a reset connection.
+            let _ = writer.shutdown().await;
+        }
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(true);
+        }
+        match self.read_task.take() {
+            Some(task) => task.await.unwrap_or(Ok(())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The messages received from the server by an AsyncEchoClient, as a Stream.
+pub struct MessageStream {
+    // The Receiver to which the read task delivers messages.
+    receiver: mpsc::UnboundedReceiver<Message>,
+}
+
+impl MessageStream {
+    /// Waits for the next message, or returns None once the client has disconnected.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for MessageStream {
+    type Item = Message;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+async fn read_process(
+    mut reader: OwnedReadHalf,
+    peer: SocketAddr,
+    framing: Framing,
+    subscribers: Arc<Mutex<Vec<mpsc::UnboundedSender<Message>>>>,
+    writer: Arc<tokio::sync::Mutex<Option<OwnedWriteHalf>>>,
+    mut shutdown: watch::Receiver<bool>,
+) -> Result<(), EchoError> {
+    let mut decoder = framing.decoder();
+    let mut buf = vec![0; BUFFER_SIZE];
+    let result = loop {
+        let len = tokio::select! {
+            result = reader.read(&mut buf) => match result {
+                Ok(0) => break Ok(()),
+                Ok(len) => len,
+                Err(e) => break Err(EchoError::Io(e)),
+            },
+            _ = shutdown.changed() => break Ok(()),
+        };
+        let messages = match decoder.decode(&buf[..len]) {
+            Ok(messages) => messages,
+            Err(e) => break Err(e),
+        };
+        for bytes in messages {
+            let message = Message::new(bytes, peer);
+            subscribers
+                .lock()
+                .unwrap()
+                .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+        }
+    };
+    // Messages can no longer be sent once the connection has ended.
+    writer.lock().await.take();
+    // Dropping the Senders ends every MessageStream.
+    subscribers.lock().unwrap().clear();
+    result
+}
@@ -0,0 +1,317 @@
+//! The protocols and transports over which an EchoClient can reach a server.
+
+use std::io;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+#[cfg(feature = "tls")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "tls")]
+use std::thread;
+use std::time::Duration;
+#[cfg(feature = "tls")]
+use std::time::Instant;
+
+#[cfg(feature = "tls")]
+use rustls::pki_types::ServerName;
+#[cfg(feature = "tls")]
+use rustls::{ClientConfig, ClientConnection};
+
+#[cfg(feature = "tls")]
+use jdn_echo_core::protocol::DELAY_MS;
+use jdn_echo_core::protocol::{BUFFER_SIZE, MAX_DATAGRAM_SIZE};
+#[cfg(feature = "tls")]
+use jdn_echo_core::tls;
+use jdn_echo_core::transport::{self, Connector};
+
+/// The time allowed for a server to complete a TLS handshake.
+#[cfg(feature = "tls")]
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The protocol used by an EchoClient to reach a server at an IP address.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// A TCP stream, with messages delimited by the client's framing.
+    #[default]
+    Tcp,
+    /// UDP datagrams, each carrying one message without framing, as in the standard Echo protocol described in RFC 862.
+    /// Since UDP has no connections, the client is considered connected as soon as its socket is ready,
+    /// and reconnects if the server reports that nothing is listening.
+    Udp,
+}
+
+/// An open connection to a server over one of the supported protocols or transports.
+pub(crate) enum Connection {
+    /// A stream opened by a Connector, such as a TCP stream or a Unix domain socket.
+    Stream(Box<dyn transport::Transport>),
+    Udp(UdpSocket),
+    /// The TLS session is shared by every handle to the connection, since reads and writes both advance it.
+    /// Its lock is never held while reading from or writing to the socket, so that a blocked read does not block
+    /// writes, and a blocked write does not keep received records from being processed. Records are written through
+    /// the shared writer, which one thread holds at a time so that they are sent in order. Data read from the socket
+    /// is kept by the handle that read it until the session takes it.
+    #[cfg(feature = "tls")]
+    Tls {
+        socket: Box<dyn transport::Transport>,
+        session: Arc<Mutex<ClientConnection>>,
+        writer: Arc<Mutex<Box<dyn transport::Transport>>>,
+        received: Vec<u8>,
+    },
+}
+
+impl Connection {
+    /// Opens a stream with the given connector, waiting up to the given timeout for it to be accepted.
+    pub(crate) fn open(connector: &dyn Connector, timeout: Duration) -> io::Result<Self> {
+        connector.connect(timeout).map(Connection::Stream)
+    }
+
+    /// Opens a UDP socket that sends to and receives from the given address.
+    pub(crate) fn open_udp(address: SocketAddr) -> io::Result<Self> {
+        let local_address = match address {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(local_address)?;
+        socket.connect(address)?;
+        Ok(Connection::Udp(socket))
+    }
+
+    /// Opens a stream with the given connector and completes a TLS handshake over it,
+    /// verifying the server's certificate for the given name.
+    #[cfg(feature = "tls")]
+    pub(crate) fn open_tls(
+        connector: &dyn Connector,
+        timeout: Duration,
+        config: &Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+    ) -> io::Result<Self> {
+        let mut socket = connector.connect(timeout)?;
+        let mut session =
+            ClientConnection::new(Arc::clone(config), server_name).map_err(tls::io_error)?;
+        // Not every stream can time out a blocking read, so the handshake polls the stream until its deadline.
+        socket.set_nonblocking(true)?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let mut delay = 1;
+        while session.is_handshaking() {
+            match session.complete_io(&mut socket) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(io::Error::from(io::ErrorKind::TimedOut));
+                    }
+                    thread::sleep(Duration::from_millis(delay));
+                    delay = (delay * 2).min(DELAY_MS);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        socket.set_nonblocking(false)?;
+        let writer = socket.try_clone()?;
+        Ok(Connection::Tls {
+            socket,
+            session: Arc::new(Mutex::new(session)),
+            writer: Arc::new(Mutex::new(writer)),
+            received: Vec::new(),
+        })
+    }
+
+    /// Gets the name of the socket type, used to name the threads serving the connection.
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Connection::Stream(stream) => stream.kind(),
+            Connection::Udp(_) => "UdpSocket",
+            #[cfg(feature = "tls")]
+            Connection::Tls { .. } => "TlsStream",
+        }
+    }
+
+    /// Gets the size of the buffer needed to read from the connection without truncating any data.
+    pub(crate) fn buffer_size(&self) -> usize {
+        match self {
+            Connection::Udp(_) => MAX_DATAGRAM_SIZE,
+            _ => BUFFER_SIZE,
+        }
+    }
+
+    /// Gets the flag that indicates if a read of zero bytes means the server closed the connection.
+    /// This is not the case for UDP, where it means an empty datagram was received.
+    pub(crate) fn is_stream(&self) -> bool {
+        !matches!(self, Connection::Udp(_))
+    }
+
+    pub(crate) fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Connection::Stream(stream) => stream.try_clone().map(Connection::Stream),
+            Connection::Udp(socket) => socket.try_clone().map(Connection::Udp),
+            #[cfg(feature = "tls")]
+            Connection::Tls {
+                socket,
+                session,
+                writer,
+                ..
+            } => Ok(Connection::Tls {
+                socket: socket.try_clone()?,
+                session: Arc::clone(session),
+                writer: Arc::clone(writer),
+                received: Vec::new(),
+            }),
+        }
+    }
+
+    /// Sets the timeout of a blocking read from a UDP socket, which shutdown cannot interrupt.
+    /// Stream reads are left without a timeout, since shutdown interrupts them.
+    pub(crate) fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        match self {
+            Connection::Udp(socket) => socket.set_read_timeout(Some(timeout)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Gets the address of the server, or a placeholder if the transport has no IP address.
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Connection::Stream(stream) => stream.peer_addr(),
+            Connection::Udp(socket) => socket.peer_addr(),
+            #[cfg(feature = "tls")]
+            Connection::Tls { socket, .. } => socket.peer_addr(),
+        }
+    }
+
+    /// Closes a stream, ending a TLS session cleanly first, which interrupts its threads.
+    /// UDP sockets have nothing to close, so their threads stop when their read times out.
+    pub(crate) fn shutdown(&self) {
+        match self {
+            Connection::Stream(stream) => {
+                let _ = stream.shutdown();
+            }
+            Connection::Udp(_) => {}
+            #[cfg(feature = "tls")]
+            Connection::Tls {
+                socket,
+                session,
+                writer,
+                ..
+            } => {
+                // The notification is skipped if a blocked write holds the writer, rather than waited for.
+                session.lock().unwrap().send_close_notify();
+                let _ = send_tls(session, writer);
+                let _ = socket.shutdown();
+            }
+        }
+    }
+
+    /// Reads the next bytes from a stream, or the next datagram from a UDP socket.
+    pub(crate) fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Stream(stream) => stream.read(buf),
+            Connection::Udp(socket) => socket.recv(buf),
+            #[cfg(feature = "tls")]
+            Connection::Tls {
+                socket,
+                session,
+                writer,
+                received,
+            } => loop {
+                let (read, processed) = {
+                    let mut session = session.lock().unwrap();
+                    let mut processed = Ok(());
+                    let read = loop {
+                        match session.reader().read(buf) {
+                            Ok(len) => break Ok(Some(len)),
+                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                            Err(e) => break Err(e),
+                        }
+                        if received.is_empty() || processed.is_err() {
+                            break Ok(None);
+                        }
+                        // The session refuses more records while it holds too much data that has not been read,
+                        // so received data is only handed to it once everything it already holds has been read.
+                        let mut rest = &received[..];
+                        session.read_tls(&mut rest)?;
+                        let taken = received.len() - rest.len();
+                        received.drain(..taken);
+                        processed = session.process_new_packets().map(|_| ());
+                    };
+                    (read, processed)
+                };
+                // Answer anything the server's records require, such as a key update, or try to tell the server
+                // why the connection is being closed.
+                let sent = send_tls(session, writer);
+                processed.map_err(tls::io_error)?;
+                if let Some(len) = read? {
+                    return Ok(len);
+                }
+                sent?;
+                let mut data = [0; BUFFER_SIZE];
+                let len = socket.read(&mut data)?;
+                if len == 0 {
+                    return Ok(0);
+                }
+                received.extend_from_slice(&data[..len]);
+            },
+        }
+    }
+
+    /// Writes all of the given data to a stream, or sends it as a single datagram from a UDP socket.
+    pub(crate) fn write_all(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            Connection::Stream(stream) => stream.write_all(data),
+            Connection::Udp(socket) => {
+                let len = socket.send(data)?;
+                if len < data.len() {
+                    return Err(io::Error::from(io::ErrorKind::WriteZero));
+                }
+                Ok(())
+            }
+            #[cfg(feature = "tls")]
+            Connection::Tls {
+                session, writer, ..
+            } => {
+                // The session only buffers a limited amount of data, so the records for each part are written
+                // before the next part is given to it.
+                let mut socket = writer.lock().unwrap();
+                let mut data = data;
+                while !data.is_empty() {
+                    let len = session.lock().unwrap().writer().write(data)?;
+                    data = &data[len..];
+                    write_records(session, &mut *socket)?;
+                }
+                drop(socket);
+                send_tls(session, writer)
+            }
+        }
+    }
+}
+
+// Writes the records the session has produced to the socket, unless another thread holding the writer is already
+// writing them. A thread checks for more records after releasing the writer, so that none produced while it held the
+// writer are left unsent.
+#[cfg(feature = "tls")]
+fn send_tls(
+    session: &Mutex<ClientConnection>,
+    writer: &Mutex<Box<dyn transport::Transport>>,
+) -> io::Result<()> {
+    loop {
+        let Ok(mut socket) = writer.try_lock() else {
+            return Ok(());
+        };
+        write_records(session, &mut *socket)?;
+        drop(socket);
+        if !session.lock().unwrap().wants_write() {
+            return Ok(());
+        }
+    }
+}
+
+// Writes the records the session has produced to the socket, until it has no more. The session is only locked to take
+// the records, never while they are written.
+#[cfg(feature = "tls")]
+fn write_records(session: &Mutex<ClientConnection>, socket: &mut dyn Write) -> io::Result<()> {
+    loop {
+        let mut records = Vec::new();
+        tls::flush_tls(&mut session.lock().unwrap(), &mut records)?;
+        if records.is_empty() {
+            return Ok(());
+        }
+        socket.write_all(&records)?;
+    }
+}
@@ -0,0 +1,161 @@
+//! The routing of messages received by an EchoClient to its subscribers and pending requests.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::protocol::{self, Message};
+
+/// The reply to a request made with EchoClient::request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Response {
+    // The echoed message, without its correlation ID.
+    message: Message,
+    // The time between queueing the request and receiving its echo.
+    round_trip: Duration,
+}
+
+impl Response {
+    /// Constructs a new Response with the given echo and round-trip time.
+    pub(crate) fn new(message: Message, round_trip: Duration) -> Self {
+        Response {
+            message,
+            round_trip,
+        }
+    }
+
+    /// Gets the echoed message, without its correlation ID.
+    pub fn message(&self) -> &Message {
+        &self.message
+    }
+
+    /// Gets the time between queueing the request and receiving its echo,
+    /// which includes any time the request spent waiting in the outbox.
+    pub fn round_trip(&self) -> Duration {
+        self.round_trip
+    }
+
+    /// Consumes the response, returning the echoed message.
+    pub fn into_message(self) -> Message {
+        self.message
+    }
+}
+
+/// A request sent with EchoClient::send_request, whose reply can be awaited without borrowing the client.
+/// Dropping it stops waiting for the reply, which is then delivered to the subscribers if it arrives.
+#[derive(Debug)]
+pub struct PendingRequest {
+    // The inbox in which the request is registered.
+    inbox: Arc<Inbox>,
+    // The correlation ID of the request.
+    id: u64,
+    // The Receiver of the reply.
+    reply_receiver: mpsc::Receiver<Message>,
+    // The time at which the request was queued.
+    start: Instant,
+}
+
+impl PendingRequest {
+    /// Registers a new request in the given inbox.
+    pub(crate) fn new(inbox: Arc<Inbox>) -> Self {
+        let (id, reply_receiver) = inbox.register();
+        PendingRequest {
+            inbox,
+            id,
+            reply_receiver,
+            start: Instant::now(),
+        }
+    }
+
+    /// Gets the correlation ID of the request.
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Waits up to the given timeout for the echo of the request. Returns the echo along with the measured
+    /// round-trip time, or TimedOut if no echo arrived in time.
+    pub fn wait(self, timeout: Duration) -> Result<Response, EchoError> {
+        match self.reply_receiver.recv_timeout(timeout) {
+            Ok(message) => Ok(Response::new(message, self.start.elapsed())),
+            Err(_) => Err(EchoError::TimedOut),
+        }
+    }
+}
+
+impl Drop for PendingRequest {
+    fn drop(&mut self) {
+        self.inbox.cancel(self.id);
+    }
+}
+
+/// Delivers each message received from the server to the request it answers, or otherwise to every subscriber.
+#[derive(Debug)]
+pub(crate) struct Inbox {
+    // The Senders used to deliver messages received from the server to each subscriber.
+    subscribers: Mutex<Vec<mpsc::Sender<Message>>>,
+    // The Senders used to deliver replies to the requests awaiting them, keyed by correlation ID.
+    pending: Mutex<HashMap<u64, mpsc::SyncSender<Message>>>,
+    // The correlation ID of the next request.
+    next_id: AtomicU64,
+}
+
+impl Inbox {
+    /// Constructs a new Inbox without subscribers or pending requests.
+    pub(crate) fn new() -> Self {
+        // Start from a random ID, so that the echoes of another client's requests are unlikely to match.
+        let first_id = RandomState::new().build_hasher().finish();
+        Inbox {
+            subscribers: Mutex::new(Vec::new()),
+            pending: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(first_id),
+        }
+    }
+
+    /// Adds a subscriber, which receives every message that does not answer a pending request.
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<Message> {
+        let (message_sender, message_receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(message_sender);
+        message_receiver
+    }
+
+    /// Registers a new request, returning its correlation ID and the Receiver of its reply.
+    pub(crate) fn register(&self) -> (u64, mpsc::Receiver<Message>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (reply_sender, reply_receiver) = mpsc::sync_channel(1);
+        self.pending.lock().unwrap().insert(id, reply_sender);
+        (id, reply_receiver)
+    }
+
+    /// Forgets a request that will no longer wait for its reply.
+    pub(crate) fn cancel(&self, id: u64) {
+        self.pending.lock().unwrap().remove(&id);
+    }
+
+    /// Delivers a message received from the given peer. A tagged message that answers a pending request is delivered
+    /// only to that request. Any other tagged message, such as the broadcast echo of another client's request,
+    /// is delivered to the subscribers without its tag.
+    pub(crate) fn deliver(&self, bytes: Vec<u8>, peer: SocketAddr) {
+        let message = match protocol::untag(&bytes) {
+            Some((id, payload)) => {
+                let message = Message::new(payload.to_vec(), peer);
+                match self.pending.lock().unwrap().remove(&id) {
+                    Some(reply_sender) => {
+                        let _ = reply_sender.try_send(message);
+                        return;
+                    }
+                    None => message,
+                }
+            }
+            None => Message::new(bytes, peer),
+        };
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(message.clone()).is_ok());
+    }
+}
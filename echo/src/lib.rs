@@ -1,187 +1,679 @@
 #![deny(missing_docs)]
 //! The simplest echo client
 
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+pub mod connection;
+pub mod inbox;
+pub mod outbox;
+pub mod reconnect;
+pub mod state;
+#[cfg(feature = "tls")]
+mod tls;
+
 use std::io;
-use std::io::{Read, Write};
-use std::net::SocketAddr;
-use std::net::TcpStream;
-use std::ops::Deref;
+#[cfg(feature = "tls")]
+use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-/// A TCP client that can send and receive text to and from an echo server.
+#[cfg(feature = "tokio")]
+pub use crate::asynchronous::{AsyncEchoClient, MessageStream};
+use crate::connection::Connection;
+pub use crate::connection::Protocol;
+use crate::inbox::Inbox;
+pub use crate::inbox::{PendingRequest, Response};
+use crate::outbox::Outbox;
+pub use crate::outbox::{OverflowPolicy, SendOutcome};
+pub use crate::reconnect::ReconnectPolicy;
+pub use crate::state::ConnectionState;
+use crate::state::StateCell;
+#[cfg(feature = "tls")]
+use crate::tls::TlsSettings;
+#[cfg(feature = "tls")]
+use rustls::pki_types::ServerName;
+#[cfg(feature = "tls")]
+use rustls::ClientConfig;
+
+pub use jdn_echo_core::endpoint::Endpoint;
+pub use jdn_echo_core::error::EchoError;
+use jdn_echo_core::framing::FrameDecoder;
+pub use jdn_echo_core::framing::{Framing, MAX_FRAME_SIZE};
+use jdn_echo_core::lifecycle::{EchoService, Lifecycle};
+pub use jdn_echo_core::protocol::Message;
+use jdn_echo_core::protocol::{self, DELAY_MS};
+use jdn_echo_core::transport::Connector;
+
+/// A TCP, UDP or Unix domain socket client that can send and receive text or bytes to and from an echo server.
 pub struct EchoClient {
-    // The address to which the client should connect.
-    address: SocketAddr,
-    // The flag that indicates if the client should be attempting to connect.
-    running: Arc<AtomicBool>,
-    // The flag that indicates if the client is successfully connected to a server.
-    connected: Arc<AtomicBool>,
-    // The Sender used to send messages to the server.
-    sender: Arc<Mutex<Option<mpsc::Sender<String>>>>,
+    // The endpoint to which the client should connect.
+    address: Endpoint,
+    // The running state shared with the client's threads: whether it should be attempting to connect, the most recent
+    // error they reported, and the Condvar that wakes them when it stops.
+    lifecycle: Lifecycle,
+    // The current stage of the connection to the server.
+    state: Arc<StateCell>,
+    // The messages waiting to be sent to the server, kept while the client is disconnected.
+    outbox: Arc<Outbox>,
+    // The subscribers and pending requests to which messages received from the server are delivered.
+    inbox: Arc<Inbox>,
+    // The framing used to delimit messages sent to and received from the server.
+    framing: Framing,
+    // The protocol used to reach the server.
+    protocol: Protocol,
+    // The connector used to reach the server instead of its address, if any.
+    connector: Option<Arc<dyn Connector>>,
+    // The policy that controls the delay between connection attempts.
+    reconnect_policy: ReconnectPolicy,
+    // The token presented to the server at the start of each connection, if any.
+    token: Option<String>,
+    // The choices that make up the TLS configuration used to connect to the server.
+    #[cfg(feature = "tls")]
+    tls: TlsSettings,
+    // The current connection, used to interrupt the connection when the client is stopped.
+    active_connection: Arc<Mutex<Option<Connection>>>,
+    // The handle of the thread that connects to the server, joined when the client is stopped.
+    connect_thread: Option<JoinHandle<()>>,
 }
 
 impl EchoClient {
-    const DELAY_MS: u64 = 100;
-
-    /// Constructs a new EchoClient with the given address.
-    pub fn new(address: SocketAddr) -> Self {
+    /// Constructs a new EchoClient with the given address or Unix domain socket path.
+    pub fn new(address: impl Into<Endpoint>) -> Self {
         EchoClient {
-            address,
-            running: Arc::new(AtomicBool::new(false)),
-            connected: Arc::new(AtomicBool::new(false)),
-            sender: Arc::new(Mutex::new(None)),
+            address: address.into(),
+            lifecycle: Lifecycle::new(),
+            state: Arc::new(StateCell::new()),
+            outbox: Arc::new(Outbox::new()),
+            inbox: Arc::new(Inbox::new()),
+            framing: Framing::default(),
+            protocol: Protocol::default(),
+            connector: None,
+            reconnect_policy: ReconnectPolicy::default(),
+            token: None,
+            #[cfg(feature = "tls")]
+            tls: TlsSettings::default(),
+            active_connection: Arc::new(Mutex::new(None)),
+            connect_thread: None,
         }
     }
 
-    /// Sets the address to which the client should connect. The change will have no effect until the next call to start.
-    pub fn set_address(&mut self, address: SocketAddr) {
-        self.address = address
+    /// Sets the address or Unix domain socket path to which the client should connect.
+    /// The change will have no effect until the next call to start.
+    pub fn set_address(&mut self, address: impl Into<Endpoint>) {
+        self.address = address.into()
+    }
+
+    /// Sets the framing used to delimit messages. The change will have no effect until the next call to start.
+    /// Framing is ignored when using UDP, where each datagram carries one message.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing
+    }
+
+    /// Sets the protocol used to reach the server. The change will have no effect until the next call to start.
+    /// To probe a standard TCP echo server, use Protocol::Tcp with Framing::Raw.
+    /// The protocol is ignored when connecting to a Unix domain socket, which is always reached as a stream.
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol
+    }
+
+    /// Sets the connector used to reach the server instead of its address, such as the MemoryConnector of an
+    /// in-memory transport, or None to connect to the address. A connector always opens a stream, so the protocol
+    /// is ignored while one is set, and TLS is used over the stream once configured.
+    /// The change will have no effect until the next call to start.
+    pub fn set_connector(&mut self, connector: Option<Arc<dyn Connector>>) {
+        self.connector = connector
+    }
+
+    /// Sets the policy that controls the delay between connection attempts, and when the client gives up.
+    /// The change will have no effect until the next call to start.
+    pub fn set_reconnect_policy(&mut self, reconnect_policy: ReconnectPolicy) {
+        self.reconnect_policy = reconnect_policy
+    }
+
+    /// Sets the token presented to the server at the start of each stream connection, or None to present no token.
+    /// The client is only connected once the server accepts the token. If the server does not accept the token,
+    /// the client stops, and take_error returns AuthenticationFailed with the server's reason.
+    /// The change will have no effect until the next call to start.
+    pub fn set_token(&mut self, token: Option<String>) {
+        self.token = token
+    }
+
+    /// Connects over TLS from the next call to start, trusting a server whose certificate chains to one of the
+    /// CA certificates in the given PEM file. The certificate must be valid for the IP address of the server, or for
+    /// the name given to set_tls_server_name, which is required to reach a server over a Unix domain socket or a
+    /// connector. Start fails over UDP, which TLS does not support. Returns an error if the file cannot be loaded.
+    #[cfg(feature = "tls")]
+    pub fn set_tls_ca(&mut self, ca_path: &Path) -> Result<(), EchoError> {
+        self.tls.set_ca(ca_path)
+    }
+
+    /// Connects over TLS from the next call to start, trusting only a server that presents the certificate
+    /// in the given PEM file, regardless of its issuer, names or validity period.
+    /// Start fails over UDP, which TLS does not support. Returns an error if the file cannot be loaded.
+    #[cfg(feature = "tls")]
+    pub fn set_tls_pinned_certificate(&mut self, cert_path: &Path) -> Result<(), EchoError> {
+        self.tls.set_pinned_certificate(cert_path)
+    }
+
+    /// Sets the DNS name or IP address the server's certificate must be valid for when it is verified against a CA,
+    /// or None to use the IP address of the server. The change will have no effect until the next call to start.
+    /// Returns an error if the name is not a valid DNS name or IP address.
+    #[cfg(feature = "tls")]
+    pub fn set_tls_server_name(&mut self, server_name: Option<&str>) -> Result<(), EchoError> {
+        self.tls.set_server_name(server_name)
+    }
+
+    /// Presents the certificate chain and private key in the given PEM files to servers that require client
+    /// certificates. Takes effect from the next call to start, once set_tls_ca or set_tls_pinned_certificate has
+    /// been called. Returns an error if the files cannot be loaded, or if the key does not match the certificate.
+    #[cfg(feature = "tls")]
+    pub fn set_tls_client_certificate(
+        &mut self,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> Result<(), EchoError> {
+        self.tls.set_client_certificate(cert_path, key_path)
     }
 
     /// Gets the flag that indicates if the client should be attempting to connect.
     pub fn is_running(&self) -> bool {
-        self.running.load(Ordering::Relaxed)
+        self.lifecycle.is_running()
     }
 
     /// Gets the flag that indicates if the client is successfully connected to a server.
     pub fn is_connected(&self) -> bool {
-        self.connected.load(Ordering::Relaxed)
+        self.state.get() == ConnectionState::Connected
+    }
+
+    /// Gets the current stage of the connection to the server.
+    pub fn state(&self) -> ConnectionState {
+        self.state.get()
+    }
+
+    /// Blocks until the connection reaches the given state, or the given timeout elapses.
+    /// Returns true if the state was reached.
+    pub fn wait_for_state(&self, state: ConnectionState, timeout: Duration) -> bool {
+        self.state.wait_for(state, timeout)
+    }
+
+    /// Subscribes to changes of the connection state. Every state entered after this call
+    /// is delivered to the returned Receiver, until it is dropped.
+    pub fn state_changes(&self) -> mpsc::Receiver<ConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Sets the number of messages that can wait to be sent. Takes effect immediately.
+    pub fn set_outbox_capacity(&self, capacity: usize) {
+        self.outbox.set_capacity(capacity)
     }
 
-    /// Sends the given message to the server. If the client is not currently connected, this method has no effect.
-    pub fn send_message(&self, message: &str) {
-        if let Some(msg_sender) = self.sender.lock().unwrap().deref() {
-            let _ = msg_sender.send(message.to_owned());
+    /// Sets what happens to a message sent while the outbox is full. Takes effect immediately.
+    pub fn set_overflow_policy(&self, overflow_policy: OverflowPolicy) {
+        self.outbox.set_overflow_policy(overflow_policy)
+    }
+
+    /// Gets the number of messages waiting to be sent.
+    pub fn queued_messages(&self) -> usize {
+        self.outbox.len()
+    }
+
+    /// Queues the given text to be sent to the server. See send_bytes.
+    pub fn send_message(&self, message: &str) -> Result<SendOutcome, EchoError> {
+        self.send_bytes(message.as_bytes())
+    }
+
+    /// Queues the given bytes to be sent to the server. Messages sent while the client is disconnected
+    /// are kept, and sent in order once it connects. Returns what happened to the message, or an error
+    /// if it was rejected by the overflow policy or is longer than MAX_FRAME_SIZE.
+    pub fn send_bytes(&self, message: &[u8]) -> Result<SendOutcome, EchoError> {
+        if message.len() > MAX_FRAME_SIZE {
+            return Err(EchoError::FrameTooLarge);
+        }
+        self.outbox.push(message.to_vec(), &self.lifecycle)
+    }
+
+    /// Subscribes to the messages received from the server. Every message received after this call
+    /// is delivered to the returned Receiver, until it is dropped.
+    /// Replies to requests made with request are not delivered to subscribers.
+    pub fn messages(&self) -> mpsc::Receiver<Message> {
+        self.inbox.subscribe()
+    }
+
+    /// Sends the given bytes to the server tagged with a correlation ID, and waits up to the given timeout for
+    /// the echo carrying the same ID. Returns the echo along with the measured round-trip time, TimedOut if no echo
+    /// arrived in time, or an error if the request was rejected by the overflow policy or is too long to frame.
+    pub fn request(&self, payload: &[u8], timeout: Duration) -> Result<Response, EchoError> {
+        self.send_request(payload)?.wait(timeout)
+    }
+
+    /// Sends the given bytes to the server tagged with a correlation ID, without waiting for the echo.
+    /// Returns a PendingRequest on which to wait for the echo, or an error if the request was rejected by the overflow
+    /// policy or is too long to frame.
+    pub fn send_request(&self, payload: &[u8]) -> Result<PendingRequest, EchoError> {
+        let request = PendingRequest::new(Arc::clone(&self.inbox));
+        let message = protocol::tag(request.id(), payload);
+        if message.len() > MAX_FRAME_SIZE {
+            return Err(EchoError::FrameTooLarge);
+        }
+        match self.outbox.push(message, &self.lifecycle)? {
+            SendOutcome::DroppedNewest => Err(EchoError::OutboxFull),
+            _ => Ok(request),
         }
     }
 
     /// Asynchronously starts the process of connecting to the server and listening for data.
-    /// If the client is already connected or attempting to connect, this method has no effect.
-    pub fn start(&mut self) {
-        if self.running.swap(true, Ordering::Relaxed) {
-            return;
-        }
-        let connect_running = Arc::clone(&self.running);
-        let connect_connected = Arc::clone(&self.connected);
-        let connect_address = self.address;
-        let sender = Arc::clone(&self.sender);
-        thread::Builder::new()
+    /// Returns an error if the client is already connected or attempting to connect, if TLS is configured but cannot
+    /// be used to reach the server, or if the process could not be started.
+    pub fn start(&mut self) -> Result<(), EchoError> {
+        self.lifecycle.begin()?;
+        if let Some(handle) = self.connect_thread.take() {
+            // The previous connect thread gave up reconnecting, and is exiting.
+            let _ = handle.join();
+        }
+        // A connector or Unix domain socket always opens a stream, whatever the protocol.
+        let is_stream = self.protocol == Protocol::Tcp
+            || self.connector.is_some()
+            || self.address.socket_addr().is_none();
+        #[cfg(feature = "tls")]
+        let tls = match self.tls_settings(is_stream) {
+            Ok(tls) => tls,
+            Err(e) => {
+                self.lifecycle.end();
+                return Err(e);
+            }
+        };
+        self.state.set(ConnectionState::Connecting);
+        let connect_lifecycle = self.lifecycle.clone();
+        let connect_state = Arc::clone(&self.state);
+        let connect_settings = ConnectSettings {
+            address: self.address.clone(),
+            framing: if is_stream {
+                self.framing
+            } else {
+                Framing::Raw
+            },
+            protocol: self.protocol,
+            connector: self.connector.clone(),
+            reconnect_policy: self.reconnect_policy.clone(),
+            token: if is_stream { self.token.clone() } else { None },
+            #[cfg(feature = "tls")]
+            tls,
+        };
+        let connect_outbox = Arc::clone(&self.outbox);
+        let connect_inbox = Arc::clone(&self.inbox);
+        let connect_active_connection = Arc::clone(&self.active_connection);
+        let spawn_result = thread::Builder::new()
             .name(String::from("JdnEcho-TcpStream-connect"))
             .spawn(move || {
-                while connect_running.load(Ordering::Relaxed) {
-                    let stream_result = TcpStream::connect_timeout(
-                        &connect_address,
-                        Duration::from_millis(Self::DELAY_MS),
-                    );
-                    match stream_result {
-                        Ok(stream) => {
-                            println!("Successfully connected to {}", connect_address);
-                            if stream.set_nonblocking(true).is_err() {
-                                continue;
-                            }
-                            connect_connected.store(true, Ordering::Relaxed);
-                            let read_running = Arc::clone(&connect_running);
-                            let read_connected = Arc::clone(&connect_connected);
-                            let read_stream = stream.try_clone().unwrap();
-                            let read_thread = thread::Builder::new()
-                                .name(String::from("JdnEcho-TcpStream-read"))
-                                .spawn(move || {
-                                    let _ = EchoClient::read_process(
-                                        read_stream,
-                                        read_running,
-                                        read_connected,
-                                    );
-                                })
-                                .expect("failed to spawn thread");
-
-                            let write_running = Arc::clone(&connect_running);
-                            let write_connected = Arc::clone(&connect_connected);
-                            let (write_sender, write_receiver) = mpsc::channel::<String>();
-                            let write_thread = thread::Builder::new()
-                                .name(String::from("JdnEcho-TcpStream-write"))
-                                .spawn(move || {
-                                    let _ = EchoClient::write_process(
-                                        stream,
-                                        write_running,
-                                        write_connected,
-                                        write_receiver,
-                                    );
-                                })
-                                .expect("failed to spawn thread");
-                            *sender.lock().unwrap() = Some(write_sender);
-
-                            let _ = read_thread.join();
-                            let _ = write_thread.join();
-                        }
-                        Err(_) => {
-                            thread::sleep(Duration::from_millis(Self::DELAY_MS));
-                        }
-                    }
-                }
-            })
-            .expect("failed to spawn thread");
+                Self::connect_process(
+                    connect_settings,
+                    connect_lifecycle,
+                    connect_state,
+                    connect_outbox,
+                    connect_inbox,
+                    connect_active_connection,
+                );
+            });
+        match spawn_result {
+            Ok(handle) => {
+                self.connect_thread = Some(handle);
+                Ok(())
+            }
+            Err(e) => {
+                self.lifecycle.end();
+                self.state.set(ConnectionState::Stopped);
+                Err(EchoError::ThreadSpawnFailed(e))
+            }
+        }
     }
 
-    /// Asynchronously disconnects from the server if a connection was established, and stops connection attempts.
+    // Gets the TLS configuration and the name the server is verified against, or None to connect in plaintext.
+    #[cfg(feature = "tls")]
+    fn tls_settings(&self, is_stream: bool) -> Result<Option<ClientTls>, EchoError> {
+        let config = match self.tls.config() {
+            Some(config) => config,
+            None => return Ok(None),
+        };
+        if !is_stream {
+            return Err(EchoError::TlsConfigFailed(String::from(
+                "TLS is not supported over UDP",
+            )));
+        }
+        // A server reached through a connector has no IP address to verify.
+        let address = match self.connector {
+            Some(_) => None,
+            None => self.address.socket_addr(),
+        };
+        Ok(Some((config, self.tls.server_name(address)?)))
+    }
+
+    /// Disconnects from the server if a connection was established, and stops connection attempts.
+    /// Waits for all of the client's threads to exit before returning.
     pub fn stop(&mut self) {
-        self.running.store(false, Ordering::Relaxed)
+        if self.lifecycle.is_running() {
+            self.state.set(ConnectionState::Stopping);
+        }
+        self.lifecycle.end();
+        if let Some(connection) = self.active_connection.lock().unwrap().take() {
+            connection.shutdown();
+        }
+        self.outbox.wake();
+        if let Some(handle) = self.connect_thread.take() {
+            let _ = handle.join();
+        }
     }
 
-    fn read_process(
-        mut stream: TcpStream,
-        read_running: Arc<AtomicBool>,
-        read_connected: Arc<AtomicBool>,
-    ) -> std::io::Result<()> {
-        let mut buf = Vec::new();
-        while read_running.load(Ordering::Relaxed) {
-            match stream.read_to_end(&mut buf) {
-                Ok(_) => {
-                    if !buf.is_empty() {
-                        match String::from_utf8(buf.drain(..).collect()) {
-                            Ok(data) => {
-                                println!("{}", data);
-                            }
-                            Err(e) => {
-                                println!("Could not parse data: {}", e);
-                            }
+    /// Takes the most recent error reported while connecting or communicating with the server,
+    /// if one has been reported since the last call.
+    pub fn take_error(&self) -> Option<EchoError> {
+        self.lifecycle.take_error()
+    }
+
+    fn connect_process(
+        settings: ConnectSettings,
+        connect_lifecycle: Lifecycle,
+        connect_state: Arc<StateCell>,
+        outbox: Arc<Outbox>,
+        inbox: Arc<Inbox>,
+        active_connection: Arc<Mutex<Option<Connection>>>,
+    ) {
+        let mut backoff = settings.reconnect_policy.backoff();
+        while connect_lifecycle.is_running() {
+            let connection = Self::open_connection(&settings);
+            match connection {
+                Ok(connection) => {
+                    backoff.reset();
+                    match connection.try_clone() {
+                        Ok(active) => *active_connection.lock().unwrap() = Some(active),
+                        Err(e) => {
+                            connect_lifecycle.report(EchoError::Io(e));
+                            connect_lifecycle.sleep(backoff.next_delay());
+                            continue;
                         }
                     }
+                    if !connect_lifecycle.is_running() {
+                        // The client was stopped while connecting, after stop looked for the active connection.
+                        break;
+                    }
+                    // A client presenting a token is only connected once the server accepts it.
+                    if settings.token.is_none() {
+                        connect_state.set_if_active(ConnectionState::Connected);
+                    }
+                    if let Err(e) = Self::session_process(
+                        connection,
+                        &connect_lifecycle,
+                        &connect_state,
+                        &settings,
+                        &outbox,
+                        &inbox,
+                    ) {
+                        connect_lifecycle.report(e);
+                    }
+                    active_connection.lock().unwrap().take();
+                    connect_state.set_if_active(ConnectionState::Reconnecting);
                 }
                 Err(e) => {
-                    if e.kind() == io::ErrorKind::WouldBlock {
-                        thread::sleep(Duration::from_millis(Self::DELAY_MS));
-                    } else {
-                        read_connected.store(false, Ordering::Relaxed);
-                        return Err(e);
+                    let error = EchoError::ConnectFailed(e);
+                    let retry = backoff.record_failure(&error);
+                    connect_lifecycle.report(error);
+                    if !retry {
+                        connect_lifecycle.end();
+                        // Wake any send blocked on a full outbox, which fails now that the client has stopped.
+                        outbox.wake();
+                        break;
                     }
                 }
             }
-            thread::sleep(Duration::from_millis(Self::DELAY_MS));
+            connect_lifecycle.sleep(backoff.next_delay());
         }
+        active_connection.lock().unwrap().take();
+        connect_state.set(ConnectionState::Stopped);
+    }
+
+    fn open_connection(settings: &ConnectSettings) -> io::Result<Connection> {
+        let timeout = Duration::from_millis(DELAY_MS);
+        // TLS runs over whichever stream reaches the server, as start rejects it over UDP.
+        #[cfg(feature = "tls")]
+        if let Some((config, server_name)) = &settings.tls {
+            return match &settings.connector {
+                Some(connector) => {
+                    Connection::open_tls(connector.as_ref(), timeout, config, server_name.clone())
+                }
+                None => {
+                    Connection::open_tls(&settings.address, timeout, config, server_name.clone())
+                }
+            };
+        }
+        if let Some(connector) = &settings.connector {
+            return Connection::open(connector.as_ref(), timeout);
+        }
+        let address = match &settings.address {
+            Endpoint::Tcp(address) => *address,
+            #[cfg(unix)]
+            Endpoint::Unix(_) => return Connection::open(&settings.address, timeout),
+        };
+        match settings.protocol {
+            Protocol::Tcp => Connection::open(&settings.address, timeout),
+            Protocol::Udp => Connection::open_udp(address),
+        }
+    }
+
+    fn session_process(
+        mut connection: Connection,
+        session_lifecycle: &Lifecycle,
+        session_state: &Arc<StateCell>,
+        settings: &ConnectSettings,
+        outbox: &Arc<Outbox>,
+        inbox: &Arc<Inbox>,
+    ) -> Result<(), EchoError> {
+        let framing = settings.framing;
+        connection.set_read_timeout(Duration::from_millis(DELAY_MS))?;
+        if let Some(token) = &settings.token {
+            // The token must be the first message, so it is sent before the write thread starts.
+            connection.write_all(&framing.encode(&protocol::auth_request(token))?)?;
+        }
+        let read_connection = connection.try_clone()?;
+        // While the token has not been answered, the state is kept to mark the client connected once it is accepted.
+        let authenticating = settings.token.as_ref().map(|_| Arc::clone(session_state));
+        // The flag that indicates if both the read and write processes are still connected.
+        let session_connected = Arc::new(AtomicBool::new(true));
+
+        let read_lifecycle = session_lifecycle.clone();
+        let read_connected = Arc::clone(&session_connected);
+        let read_inbox = Arc::clone(inbox);
+        let read_outbox = Arc::clone(outbox);
+        let read_thread = thread::Builder::new()
+            .name(format!("JdnEcho-{}-read", connection.kind()))
+            .spawn(move || {
+                let read_result = EchoClient::read_process(
+                    read_connection,
+                    read_lifecycle.clone(),
+                    read_connected,
+                    framing.decoder(),
+                    authenticating,
+                    read_inbox,
+                    read_outbox,
+                );
+                if let Err(e) = read_result {
+                    read_lifecycle.report(e);
+                }
+            });
+        let read_thread = match read_thread {
+            Ok(handle) => handle,
+            Err(e) => return Err(EchoError::ThreadSpawnFailed(e)),
+        };
+
+        let write_lifecycle = session_lifecycle.clone();
+        let write_connected = Arc::clone(&session_connected);
+        let write_connection = connection.try_clone()?;
+        let write_outbox = Arc::clone(outbox);
+        let write_thread = thread::Builder::new()
+            .name(format!("JdnEcho-{}-write", connection.kind()))
+            .spawn(move || {
+                let write_result = EchoClient::write_process(
+                    write_connection,
+                    write_lifecycle.clone(),
+                    write_connected,
+                    write_outbox,
+                    framing,
+                );
+                if let Err(e) = write_result {
+                    write_lifecycle.report(EchoError::Io(e));
+                }
+            });
+        let write_thread = match write_thread {
+            Ok(handle) => handle,
+            Err(e) => {
+                session_connected.store(false, Ordering::Relaxed);
+                connection.shutdown();
+                let _ = read_thread.join();
+                return Err(EchoError::ThreadSpawnFailed(e));
+            }
+        };
+
+        let _ = read_thread.join();
+        let _ = write_thread.join();
         Ok(())
     }
 
+    fn read_process(
+        mut connection: Connection,
+        read_lifecycle: Lifecycle,
+        read_connected: Arc<AtomicBool>,
+        mut decoder: FrameDecoder,
+        mut authenticating: Option<Arc<StateCell>>,
+        read_inbox: Arc<Inbox>,
+        read_outbox: Arc<Outbox>,
+    ) -> Result<(), EchoError> {
+        let peer = connection.peer_addr()?;
+        let mut buf = vec![0; connection.buffer_size()];
+        let result = 'read: loop {
+            if !read_lifecycle.is_running() || !read_connected.load(Ordering::Relaxed) {
+                break Ok(());
+            }
+            match connection.read(&mut buf) {
+                Ok(0) if connection.is_stream() => break Ok(()),
+                Ok(len) => {
+                    let messages = match decoder.decode(&buf[..len]) {
+                        Ok(messages) => messages,
+                        Err(e) => break Err(e),
+                    };
+                    for message in messages {
+                        // Only the server's first message can deny the token, so that a peer cannot stop the client
+                        // by sending a message that looks like a denial.
+                        let denial = match authenticating.take() {
+                            Some(state) => {
+                                if message == protocol::ACCEPTED {
+                                    state.set_if_active(ConnectionState::Connected);
+                                    continue;
+                                }
+                                protocol::denial_reason(&message)
+                            }
+                            None => None,
+                        };
+                        if let Some(reason) = denial {
+                            // Reconnecting would present the same token, so the client stops.
+                            read_lifecycle
+                                .report(EchoError::AuthenticationFailed(reason.into_owned()));
+                            read_lifecycle.end();
+                            break 'read Ok(());
+                        }
+                        read_inbox.deliver(message, peer);
+                    }
+                }
+                // A UDP read times out periodically, so that the thread notices when the client stops.
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => break Err(EchoError::Io(e)),
+            }
+        };
+        // Wake the write thread, which is waiting for a message to send.
+        read_connected.store(false, Ordering::Relaxed);
+        read_outbox.wake();
+        result
+    }
+
     fn write_process(
-        mut stream: TcpStream,
-        write_running: Arc<AtomicBool>,
+        mut connection: Connection,
+        write_lifecycle: Lifecycle,
         write_connected: Arc<AtomicBool>,
-        write_receiver: mpsc::Receiver<String>,
+        write_outbox: Arc<Outbox>,
+        framing: Framing,
     ) -> std::io::Result<()> {
-        while write_running.load(Ordering::Relaxed) {
-            if let Ok(msg) = write_receiver.recv_timeout(Duration::from_millis(Self::DELAY_MS)) {
-                if let Err(e) = stream.write_all(msg.as_bytes()) {
-                    write_connected.store(false, Ordering::Relaxed);
-                    return Err(e);
+        let is_active = || write_lifecycle.is_running() && write_connected.load(Ordering::Relaxed);
+        while let Some(msg) = write_outbox.take(is_active) {
+            let frame = match framing.encode(&msg) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    // The message can never be sent, so it is dropped rather than kept.
+                    write_lifecycle.report(e);
+                    continue;
                 }
-            }
-            if !write_connected.load(Ordering::Relaxed) {
-                break;
+            };
+            if let Err(e) = connection.write_all(&frame) {
+                // Keep the message, so that it is sent again once the client reconnects.
+                write_outbox.restore(msg);
+                write_connected.store(false, Ordering::Relaxed);
+                // Wake the read thread, which is blocked reading from the connection.
+                connection.shutdown();
+                return Err(e);
             }
         }
         Ok(())
     }
 }
+
+// The TLS configuration used to connect to a server, along with the name its certificate is verified against.
+#[cfg(feature = "tls")]
+type ClientTls = (Arc<ClientConfig>, ServerName<'static>);
+
+// The settings captured when the client is started, used by its connect thread.
+struct ConnectSettings {
+    // The endpoint to which the client should connect.
+    address: Endpoint,
+    // The framing used to delimit messages sent to and received from the server.
+    framing: Framing,
+    // The protocol used to reach the server.
+    protocol: Protocol,
+    // The connector used to reach the server instead of its address, if any.
+    connector: Option<Arc<dyn Connector>>,
+    // The policy that controls the delay between connection attempts.
+    reconnect_policy: ReconnectPolicy,
+    // The token presented to the server at the start of each connection, if any.
+    token: Option<String>,
+    // The TLS configuration and server name used to connect to the server, or None to connect in plaintext.
+    #[cfg(feature = "tls")]
+    tls: Option<ClientTls>,
+}
+
+impl EchoService for EchoClient {
+    fn set_address(&mut self, address: Endpoint) {
+        EchoClient::set_address(self, address)
+    }
+
+    fn is_running(&self) -> bool {
+        EchoClient::is_running(self)
+    }
+
+    fn start(&mut self) -> Result<(), EchoError> {
+        EchoClient::start(self)
+    }
+
+    fn stop(&mut self) {
+        EchoClient::stop(self)
+    }
+
+    fn take_error(&self) -> Option<EchoError> {
+        EchoClient::take_error(self)
+    }
+}
+
+impl Drop for EchoClient {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -1,14 +1,16 @@
-mod lib;
-
 use std::io::Write;
-use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 use jdn_cli::manager::CliManager;
 use jdn_cli::{CliError, CliHandler};
+use jdn_echo_core::cli;
 
-use crate::lib::EchoClient;
+use jdn_echo::{EchoClient, Endpoint, SendOutcome};
 
 fn main() {
     let echo_handler = Arc::new(EchoCliHandler::new());
@@ -17,38 +19,66 @@ fn main() {
     cli_manager.start();
 }
 
-const SET_ADDRESS_COMMAND: &str = "set-address";
-const IS_RUNNING_COMMAND: &str = "is-running";
 const IS_CONNECTED_COMMAND: &str = "is-connected";
 const SEND_MESSAGE_COMMAND: &str = "send-message";
-const START_COMMAND: &str = "start";
-const STOP_COMMAND: &str = "stop";
-const COMMANDS: [&str; 6] = [
-    SET_ADDRESS_COMMAND,
-    IS_RUNNING_COMMAND,
+const REQUEST_COMMAND: &str = "request";
+const SET_TOKEN_COMMAND: &str = "set-token";
+const COMMANDS: [&str; 4] = [
     IS_CONNECTED_COMMAND,
     SEND_MESSAGE_COMMAND,
-    START_COMMAND,
-    STOP_COMMAND,
+    REQUEST_COMMAND,
+    SET_TOKEN_COMMAND,
+];
+#[cfg(feature = "tls")]
+const SET_TLS_SERVER_NAME_COMMAND: &str = "set-tls-server-name";
+#[cfg(feature = "tls")]
+const TLS_COMMANDS: [&str; 4] = [
+    cli::SET_TLS_CERT_COMMAND,
+    cli::SET_TLS_CA_COMMAND,
+    cli::SET_TLS_CLIENT_CERT_COMMAND,
+    SET_TLS_SERVER_NAME_COMMAND,
 ];
 
+// The time to wait for the echo of a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
 struct EchoCliHandler {
     client: Mutex<EchoClient>,
 }
 
 impl EchoCliHandler {
     pub fn new() -> Self {
+        let client = EchoClient::new(Endpoint::from_str("127.0.0.1:8080").unwrap());
+        let messages = client.messages();
+        thread::Builder::new()
+            .name(String::from("JdnEcho-messages-print"))
+            .spawn(move || {
+                for message in messages {
+                    println!("{}", message.text_lossy());
+                }
+            })
+            .expect("failed to spawn thread");
+        let state_changes = client.state_changes();
+        thread::Builder::new()
+            .name(String::from("JdnEcho-state-print"))
+            .spawn(move || {
+                for state in state_changes {
+                    println!("Connection {}", state);
+                }
+            })
+            .expect("failed to spawn thread");
         EchoCliHandler {
-            client: Mutex::new(EchoClient::new(
-                SocketAddr::from_str("127.0.0.1:8080").unwrap(),
-            )),
+            client: Mutex::new(client),
         }
     }
 }
 
 impl CliHandler for EchoCliHandler {
     fn get_commands(&self) -> std::collections::HashSet<&'static str> {
-        COMMANDS.iter().cloned().collect()
+        let commands = cli::COMMON_COMMANDS.iter().chain(COMMANDS.iter());
+        #[cfg(feature = "tls")]
+        let commands = commands.chain(TLS_COMMANDS.iter());
+        commands.cloned().collect()
     }
 
     fn handle_command(
@@ -57,47 +87,94 @@ impl CliHandler for EchoCliHandler {
         args: Vec<String>,
         writer: &mut dyn Write,
     ) -> Result<(), CliError> {
+        if let Some(result) = cli::handle_common_command(&self.client, command, &args, writer) {
+            return result;
+        }
         match command {
-            SET_ADDRESS_COMMAND => {
-                if let Some(address) = args.get(0) {
-                    self.client.lock().unwrap().set_address(
-                        SocketAddr::from_str(address)
-                            .map_err(|e| CliError::ArgumentParseFailure(e.to_string()))?,
-                    );
-                } else {
-                    return Err(CliError::InvalidNumberOfArguments {
-                        min: 1,
-                        max: None,
-                        given: 0,
-                    });
-                }
+            IS_CONNECTED_COMMAND => {
+                cli::write_output(writer, self.client.lock().unwrap().is_connected())?;
             }
-            IS_RUNNING_COMMAND => {
-                writeln!(writer, "{}", self.client.lock().unwrap().is_running()).map_err(|_| {
-                    CliError::ExecutionError(String::from("Unable to write output"))
-                })?;
+            SEND_MESSAGE_COMMAND => {
+                let message = cli::first_argument(&args)?;
+                let outcome = self
+                    .client
+                    .lock()
+                    .unwrap()
+                    .send_message(message)
+                    .map_err(|e| CliError::ExecutionError(format!("Unable to send: {}", e)))?;
+                if outcome != SendOutcome::Queued {
+                    cli::write_output(writer, format!("Message {}", outcome))?;
+                }
             }
-            IS_CONNECTED_COMMAND => {
-                writeln!(writer, "{}", self.client.lock().unwrap().is_connected()).map_err(
-                    |_| CliError::ExecutionError(String::from("Unable to write output")),
+            REQUEST_COMMAND => {
+                let message = cli::first_argument(&args)?;
+                // Wait for the echo without holding the lock, so other commands can run in the meantime
+                let request = self.client.lock().unwrap().send_request(message.as_bytes());
+                let response = request
+                    .and_then(|request| request.wait(REQUEST_TIMEOUT))
+                    .map_err(|e| CliError::ExecutionError(format!("Unable to request: {}", e)))?;
+                cli::write_output(
+                    writer,
+                    format!(
+                        "{} ({:.3} ms)",
+                        response.message().text_lossy(),
+                        response.round_trip().as_secs_f64() * 1000.0
+                    ),
                 )?;
             }
-            SEND_MESSAGE_COMMAND => {
-                if let Some(message) = args.get(0) {
-                    self.client.lock().unwrap().send_message(message);
-                } else {
+            SET_TOKEN_COMMAND => {
+                // Without an argument, the client stops presenting a token.
+                if args.len() > 1 {
                     return Err(CliError::InvalidNumberOfArguments {
-                        min: 1,
-                        max: None,
-                        given: 0,
+                        min: 0,
+                        max: Some(1),
+                        given: args.len(),
                     });
                 }
+                self.client.lock().unwrap().set_token(args.first().cloned());
+            }
+            #[cfg(feature = "tls")]
+            cli::SET_TLS_CERT_COMMAND => {
+                let cert_path = cli::first_argument(&args)?;
+                self.client
+                    .lock()
+                    .unwrap()
+                    .set_tls_pinned_certificate(Path::new(cert_path))
+                    .map_err(|e| CliError::ExecutionError(e.to_string()))?;
             }
-            START_COMMAND => {
-                self.client.lock().unwrap().start();
+            #[cfg(feature = "tls")]
+            cli::SET_TLS_CA_COMMAND => {
+                let ca_path = cli::first_argument(&args)?;
+                self.client
+                    .lock()
+                    .unwrap()
+                    .set_tls_ca(Path::new(ca_path))
+                    .map_err(|e| CliError::ExecutionError(e.to_string()))?;
             }
-            STOP_COMMAND => {
-                self.client.lock().unwrap().stop();
+            #[cfg(feature = "tls")]
+            cli::SET_TLS_CLIENT_CERT_COMMAND => {
+                let (cert_path, key_path) = cli::two_arguments(&args)?;
+                self.client
+                    .lock()
+                    .unwrap()
+                    .set_tls_client_certificate(Path::new(cert_path), Path::new(key_path))
+                    .map_err(|e| CliError::ExecutionError(e.to_string()))?;
+            }
+            #[cfg(feature = "tls")]
+            SET_TLS_SERVER_NAME_COMMAND => {
+                // Without an argument, the server's certificate is verified against its IP address.
+                if args.len() > 1 {
+                    return Err(CliError::InvalidNumberOfArguments {
+                        min: 0,
+                        max: Some(1),
+                        given: args.len(),
+                    });
+                }
+                self.client
+                    .lock()
+                    .unwrap()
+                    .set_tls_server_name(args.first().map(String::as_str))
+                    .map_err(|e| CliError::ExecutionError(e.to_string()))?;
             }
             _ => {
                 return Err(CliError::ExecutionError(format!(
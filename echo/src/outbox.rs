@@ -0,0 +1,158 @@
+//! The queue of messages waiting to be sent to the server.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Condvar, Mutex};
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::lifecycle::Lifecycle;
+
+/// The default number of messages the outbox can hold.
+pub const DEFAULT_OUTBOX_CAPACITY: usize = 1024;
+
+/// What the client does with a message sent while its outbox is full.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room for the new one.
+    #[default]
+    DropOldest,
+    /// Discard the new message.
+    DropNewest,
+    /// Wait until there is room for the new message, or the client is stopped.
+    Block,
+    /// Reject the new message with EchoError::OutboxFull.
+    Error,
+}
+
+/// What happened to a message passed to EchoClient::send_message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// The message was queued, and will be sent once the client is connected.
+    Queued,
+    /// The message was queued, and the oldest queued message was discarded to make room for it.
+    DroppedOldest,
+    /// The outbox was full, so the message was discarded.
+    DroppedNewest,
+}
+
+impl fmt::Display for SendOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            SendOutcome::Queued => "queued",
+            SendOutcome::DroppedOldest => "queued, oldest queued message dropped",
+            SendOutcome::DroppedNewest => "dropped, outbox full",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// A bounded queue of messages, which keeps its contents while the client is disconnected.
+pub(crate) struct Outbox {
+    // The queued messages, along with the limits applied to them.
+    queue: Mutex<OutboxQueue>,
+    // The Condvar notified when a message is queued or removed.
+    changed: Condvar,
+}
+
+struct OutboxQueue {
+    // The messages waiting to be sent, oldest first.
+    messages: VecDeque<Vec<u8>>,
+    // The number of messages the queue can hold.
+    capacity: usize,
+    // What to do with a message queued while the queue is full.
+    overflow_policy: OverflowPolicy,
+}
+
+impl Outbox {
+    /// Constructs a new, empty Outbox with the default capacity and overflow policy.
+    pub(crate) fn new() -> Self {
+        Outbox {
+            queue: Mutex::new(OutboxQueue {
+                messages: VecDeque::new(),
+                capacity: DEFAULT_OUTBOX_CAPACITY,
+                overflow_policy: OverflowPolicy::default(),
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Sets the number of messages the outbox can hold. Messages already queued beyond the new capacity are kept.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        self.queue.lock().unwrap().capacity = capacity.max(1);
+        self.changed.notify_all();
+    }
+
+    /// Sets what to do with a message queued while the outbox is full.
+    pub(crate) fn set_overflow_policy(&self, overflow_policy: OverflowPolicy) {
+        self.queue.lock().unwrap().overflow_policy = overflow_policy;
+        self.changed.notify_all();
+    }
+
+    /// Queues the given message, applying the overflow policy if the outbox is full.
+    /// A blocked call returns NotRunning once the given Lifecycle ends and the outbox is woken.
+    pub(crate) fn push(
+        &self,
+        message: Vec<u8>,
+        lifecycle: &Lifecycle,
+    ) -> Result<SendOutcome, EchoError> {
+        let mut queue = self.queue.lock().unwrap();
+        let mut outcome = SendOutcome::Queued;
+        while queue.messages.len() >= queue.capacity {
+            match queue.overflow_policy {
+                OverflowPolicy::DropOldest => {
+                    queue.messages.pop_front();
+                    outcome = SendOutcome::DroppedOldest;
+                }
+                OverflowPolicy::DropNewest => return Ok(SendOutcome::DroppedNewest),
+                OverflowPolicy::Error => return Err(EchoError::OutboxFull),
+                OverflowPolicy::Block => {
+                    if !lifecycle.is_running() {
+                        return Err(EchoError::NotRunning);
+                    }
+                    queue = self.changed.wait(queue).unwrap();
+                }
+            }
+        }
+        queue.messages.push_back(message);
+        self.changed.notify_all();
+        Ok(outcome)
+    }
+
+    /// Waits for a message, removing and returning the oldest one. Returns None once the given function
+    /// reports that the caller is no longer active, which is checked each time the outbox is woken.
+    pub(crate) fn take(&self, is_active: impl Fn() -> bool) -> Option<Vec<u8>> {
+        let queue = self.queue.lock().unwrap();
+        let mut queue = self
+            .changed
+            .wait_while(queue, |queue| is_active() && queue.messages.is_empty())
+            .unwrap();
+        if !is_active() {
+            return None;
+        }
+        let message = queue.messages.pop_front();
+        if message.is_some() {
+            self.changed.notify_all();
+        }
+        message
+    }
+
+    /// Returns a taken message to the front of the outbox, after it could not be sent.
+    /// The message is restored even if the outbox is full, so that it is not lost.
+    pub(crate) fn restore(&self, message: Vec<u8>) {
+        self.queue.lock().unwrap().messages.push_front(message);
+        self.changed.notify_all();
+    }
+
+    /// Wakes every call waiting in push or take, so that they check whether the client is still running.
+    /// The caller must change the state those calls check before waking them.
+    pub(crate) fn wake(&self) {
+        // Holding the lock ensures a waiter is either already waiting, or has yet to check the changed state.
+        let _queue = self.queue.lock().unwrap();
+        self.changed.notify_all();
+    }
+
+    /// Gets the number of messages waiting to be sent.
+    pub(crate) fn len(&self) -> usize {
+        self.queue.lock().unwrap().messages.len()
+    }
+}
@@ -0,0 +1,167 @@
+//! The policy that controls how an EchoClient reconnects to a server.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::protocol::DELAY_MS;
+
+/// The default upper bound of the delay between reconnection attempts, in milliseconds.
+pub const MAX_RECONNECT_DELAY_MS: u64 = 5000;
+
+// The callback invoked with the number of failed attempts and the last error when the client gives up.
+type GiveUpCallback = Arc<dyn Fn(u32, &EchoError) + Send + Sync>;
+
+/// Controls the delay between an EchoClient's connection attempts, and when it gives up.
+///
+/// The client waits before every attempt after the first, including after an established connection
+/// drops. The delay starts at the initial delay, grows after each attempt up to the maximum delay,
+/// and returns to the initial delay once a connection is established.
+#[derive(Clone)]
+pub struct ReconnectPolicy {
+    // The delay before the first reconnection attempt.
+    initial_delay: Duration,
+    // The upper bound of the delay between attempts.
+    max_delay: Duration,
+    // The factor applied to the delay after each attempt.
+    multiplier: u32,
+    // The fraction of each delay that is randomized, between 0 and 1.
+    jitter: f64,
+    // The number of consecutive failed attempts after which the client gives up, if any.
+    max_attempts: Option<u32>,
+    // The callback invoked when the client gives up.
+    on_give_up: Option<GiveUpCallback>,
+}
+
+impl ReconnectPolicy {
+    /// Constructs a policy that waits the same delay between every attempt, and never gives up.
+    pub fn fixed(delay: Duration) -> Self {
+        ReconnectPolicy {
+            initial_delay: delay,
+            max_delay: delay,
+            multiplier: 1,
+            jitter: 0.0,
+            max_attempts: None,
+            on_give_up: None,
+        }
+    }
+
+    /// Constructs a policy that doubles the delay after each attempt, from the initial delay
+    /// up to the maximum delay, and never gives up.
+    pub fn exponential(initial_delay: Duration, max_delay: Duration) -> Self {
+        ReconnectPolicy {
+            initial_delay,
+            max_delay: max_delay.max(initial_delay),
+            multiplier: 2,
+            ..ReconnectPolicy::fixed(initial_delay)
+        }
+    }
+
+    /// Randomly shortens each delay by up to the given fraction of it, so that many clients
+    /// reconnecting to the same server spread out their attempts. The fraction is clamped between 0 and 1.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Gives up after the given number of consecutive failed attempts, stopping the client.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Sets the callback invoked when the client gives up, with the number of failed attempts and the last error.
+    pub fn on_give_up<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(u32, &EchoError) + Send + Sync + 'static,
+    {
+        self.on_give_up = Some(Arc::new(callback));
+        self
+    }
+
+    pub(crate) fn backoff(&self) -> Backoff {
+        Backoff {
+            policy: self.clone(),
+            attempts: 0,
+            delay: self.initial_delay,
+            seed: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or_default()
+                | 1,
+        }
+    }
+}
+
+impl Default for ReconnectPolicy {
+    /// An exponential policy from DELAY_MS up to MAX_RECONNECT_DELAY_MS, which never gives up.
+    fn default() -> Self {
+        ReconnectPolicy::exponential(
+            Duration::from_millis(DELAY_MS),
+            Duration::from_millis(MAX_RECONNECT_DELAY_MS),
+        )
+    }
+}
+
+impl fmt::Debug for ReconnectPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectPolicy")
+            .field("initial_delay", &self.initial_delay)
+            .field("max_delay", &self.max_delay)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .field("max_attempts", &self.max_attempts)
+            .finish()
+    }
+}
+
+/// The progress of a client through its ReconnectPolicy.
+pub(crate) struct Backoff {
+    // The policy being followed.
+    policy: ReconnectPolicy,
+    // The number of consecutive failed attempts.
+    attempts: u32,
+    // The delay before the next attempt, before jitter is applied.
+    delay: Duration,
+    // The state of the random number generator used for jitter.
+    seed: u64,
+}
+
+impl Backoff {
+    /// Returns to the initial delay, after a connection is established.
+    pub(crate) fn reset(&mut self) {
+        self.attempts = 0;
+        self.delay = self.policy.initial_delay;
+    }
+
+    /// Records a failed attempt. Returns false, after invoking the give-up callback,
+    /// if the policy allows no further attempts.
+    pub(crate) fn record_failure(&mut self, error: &EchoError) -> bool {
+        self.attempts = self.attempts.saturating_add(1);
+        match self.policy.max_attempts {
+            Some(max_attempts) if self.attempts >= max_attempts => {
+                if let Some(callback) = &self.policy.on_give_up {
+                    callback(self.attempts, error);
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Gets the delay to wait before the next attempt, with jitter applied, and grows the delay for the attempt after.
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let delay = self.delay;
+        self.delay = (delay * self.policy.multiplier).min(self.policy.max_delay);
+        if self.policy.jitter == 0.0 {
+            return delay;
+        }
+        // xorshift64, which is plenty to spread out reconnection attempts.
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        let random = (self.seed >> 11) as f64 / (1u64 << 53) as f64;
+        delay.mul_f64(1.0 - self.policy.jitter * random)
+    }
+}
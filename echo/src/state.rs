@@ -0,0 +1,107 @@
+//! The observable connection state of an EchoClient.
+
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// The stages of an EchoClient's connection to a server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// The client has never been started.
+    Idle,
+    /// The client has been started and is attempting to connect for the first time.
+    Connecting,
+    /// The client is connected to a server, which has accepted its token if it presented one.
+    Connected,
+    /// The client lost its connection and is attempting to connect again.
+    Reconnecting,
+    /// The client has been asked to stop and is closing its connection.
+    Stopping,
+    /// The client has stopped and is no longer attempting to connect.
+    Stopped,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConnectionState::Idle => "idle",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Reconnecting => "reconnecting",
+            ConnectionState::Stopping => "stopping",
+            ConnectionState::Stopped => "stopped",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The current ConnectionState of a client, shared between the client and its background threads.
+pub(crate) struct StateCell {
+    // The current state.
+    state: Mutex<ConnectionState>,
+    // The Condvar notified whenever the state changes.
+    changed: Condvar,
+    // The Senders used to notify each subscriber of state changes.
+    subscribers: Mutex<Vec<mpsc::Sender<ConnectionState>>>,
+}
+
+impl StateCell {
+    pub(crate) fn new() -> Self {
+        StateCell {
+            state: Mutex::new(ConnectionState::Idle),
+            changed: Condvar::new(),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn get(&self) -> ConnectionState {
+        *self.state.lock().unwrap()
+    }
+
+    // Moves to the given state, notifying waiters and subscribers if the state changed.
+    pub(crate) fn set(&self, new_state: ConnectionState) {
+        self.transition(|_| true, new_state);
+    }
+
+    // Moves to the given state unless the client is stopping or stopped. Used by background threads, so that
+    // a connection completing during a call to stop does not hide the stop.
+    pub(crate) fn set_if_active(&self, new_state: ConnectionState) {
+        self.transition(
+            |current| current != ConnectionState::Stopping && current != ConnectionState::Stopped,
+            new_state,
+        );
+    }
+
+    pub(crate) fn wait_for(&self, expected: ConnectionState, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        while *state != expected {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            state = self.changed.wait_timeout(state, deadline - now).unwrap().0;
+        }
+        true
+    }
+
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<ConnectionState> {
+        let (state_sender, state_receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(state_sender);
+        state_receiver
+    }
+
+    fn transition(&self, allowed: impl Fn(ConnectionState) -> bool, new_state: ConnectionState) {
+        let mut state = self.state.lock().unwrap();
+        if *state == new_state || !allowed(*state) {
+            return;
+        }
+        *state = new_state;
+        self.changed.notify_all();
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(new_state).is_ok());
+    }
+}
@@ -0,0 +1,203 @@
+//! The TLS configuration of an EchoClient: how it verifies the server's certificate, and the certificate it
+//! presents to servers that authenticate their clients. Available with the `tls` feature.
+
+use std::convert::TryFrom;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, WebPkiSupportedAlgorithms};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, DigitallySignedStruct, Error, RootCertStore, SignatureScheme,
+};
+
+use jdn_echo_core::error::EchoError;
+use jdn_echo_core::tls::{self, clone_identity, Identity};
+
+/// The choices that make up a client's TLS configuration, along with the configuration built from them.
+#[derive(Default)]
+pub(crate) struct TlsSettings {
+    // The way the server's certificate is verified, or None to connect in plaintext.
+    verification: Option<Verification>,
+    // The certificate chain and private key presented to the server, if any.
+    identity: Option<Identity>,
+    // The name the server's certificate must be valid for, instead of the IP address of the server.
+    server_name: Option<ServerName<'static>>,
+    // The configuration built from the current choices.
+    config: Option<Arc<ClientConfig>>,
+}
+
+/// The ways a client can verify the server's certificate.
+#[derive(Clone)]
+enum Verification {
+    /// The certificate must chain to one of the given CA certificates.
+    Ca(Arc<RootCertStore>),
+    /// The certificate must be the given one.
+    Pinned(Arc<PinnedCertificate>),
+}
+
+impl TlsSettings {
+    /// Trusts a server whose certificate chains to one of the CA certificates in the given PEM file.
+    pub(crate) fn set_ca(&mut self, ca_path: &Path) -> Result<(), EchoError> {
+        let mut roots = RootCertStore::empty();
+        for certificate in tls::load_certificates(ca_path)? {
+            roots
+                .add(certificate)
+                .map_err(|e| EchoError::TlsConfigFailed(format!("{}: {}", ca_path.display(), e)))?;
+        }
+        let identity = self.identity.as_ref().map(clone_identity);
+        self.update(Some(Verification::Ca(Arc::new(roots))), identity)
+    }
+
+    /// Trusts only a server presenting the certificate in the given PEM file.
+    pub(crate) fn set_pinned_certificate(&mut self, cert_path: &Path) -> Result<(), EchoError> {
+        let verifier = PinnedCertificate {
+            certificate: tls::load_certificates(cert_path)?.remove(0),
+            algorithms: tls::crypto_provider().signature_verification_algorithms,
+        };
+        let identity = self.identity.as_ref().map(clone_identity);
+        self.update(Some(Verification::Pinned(Arc::new(verifier))), identity)
+    }
+
+    /// Presents the certificate chain and private key in the given PEM files to the server.
+    pub(crate) fn set_client_certificate(
+        &mut self,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> Result<(), EchoError> {
+        let identity = (
+            tls::load_certificates(cert_path)?,
+            tls::load_private_key(key_path)?,
+        );
+        self.update(self.verification.clone(), Some(identity))
+    }
+
+    /// Sets the name the server's certificate must be valid for, or None to use the IP address of the server.
+    pub(crate) fn set_server_name(&mut self, server_name: Option<&str>) -> Result<(), EchoError> {
+        self.server_name = match server_name {
+            Some(name) => Some(
+                ServerName::try_from(name.to_string())
+                    .map_err(|e| EchoError::TlsConfigFailed(format!("{}: {}", name, e)))?,
+            ),
+            None => None,
+        };
+        Ok(())
+    }
+
+    /// Gets the name the server's certificate is verified against, given the IP address of the server if it has one.
+    /// Returns an error if the certificate is verified against a CA, but there is neither a name nor an address.
+    pub(crate) fn server_name(
+        &self,
+        address: Option<SocketAddr>,
+    ) -> Result<ServerName<'static>, EchoError> {
+        match (&self.server_name, address, &self.verification) {
+            (Some(server_name), _, _) => Ok(server_name.clone()),
+            (None, Some(address), _) => Ok(ServerName::IpAddress(address.ip().into())),
+            // A pinned certificate is trusted regardless of its names, so any name will do.
+            (None, None, Some(Verification::Pinned(_))) => {
+                Ok(ServerName::IpAddress(Ipv4Addr::UNSPECIFIED.into()))
+            }
+            (None, None, _) => Err(EchoError::TlsConfigFailed(String::from(
+                "a server name is required to verify a server without an IP address",
+            ))),
+        }
+    }
+
+    /// Gets the configuration built from the current choices, or None if the client should connect in plaintext.
+    pub(crate) fn config(&self) -> Option<Arc<ClientConfig>> {
+        self.config.clone()
+    }
+
+    // Builds the configuration for the given choices, keeping them only if it is valid.
+    fn update(
+        &mut self,
+        verification: Option<Verification>,
+        identity: Option<Identity>,
+    ) -> Result<(), EchoError> {
+        self.config = match &verification {
+            Some(verification) => Some(build_config(verification, identity.as_ref())?),
+            None => None,
+        };
+        self.verification = verification;
+        self.identity = identity;
+        Ok(())
+    }
+}
+
+fn build_config(
+    verification: &Verification,
+    identity: Option<&Identity>,
+) -> Result<Arc<ClientConfig>, EchoError> {
+    let builder = ClientConfig::builder_with_provider(tls::crypto_provider())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| EchoError::TlsConfigFailed(e.to_string()))?;
+    let builder = match verification {
+        Verification::Ca(roots) => builder.with_root_certificates(Arc::clone(roots)),
+        Verification::Pinned(verifier) => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::clone(verifier) as Arc<dyn ServerCertVerifier>),
+    };
+    let config = match identity {
+        Some(identity) => {
+            let (certificates, key) = clone_identity(identity);
+            builder
+                .with_client_auth_cert(certificates, key)
+                .map_err(|e| EchoError::TlsConfigFailed(e.to_string()))?
+        }
+        None => builder.with_no_client_auth(),
+    };
+    Ok(Arc::new(config))
+}
+
+/// A verifier that accepts exactly one server certificate, regardless of its issuer, names or validity period.
+/// The server must still prove that it holds the certificate's private key.
+#[derive(Debug)]
+struct PinnedCertificate {
+    // The only certificate the server may present.
+    certificate: CertificateDer<'static>,
+    // The algorithms used to verify the server's handshake signatures.
+    algorithms: WebPkiSupportedAlgorithms,
+}
+
+impl ServerCertVerifier for PinnedCertificate {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, Error> {
+        if end_entity.as_ref() == self.certificate.as_ref() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls12_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        verify_tls13_signature(message, cert, dss, &self.algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.algorithms.supported_schemes()
+    }
+}
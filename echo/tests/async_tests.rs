@@ -0,0 +1,106 @@
+#![cfg(feature = "tokio")]
+
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::timeout;
+
+use jdn_echo::{AsyncEchoClient, EchoError, Framing};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[tokio::test]
+async fn test_async_client() {
+    let listener = TcpListener::bind(SocketAddr::from_str("127.0.0.1:0").unwrap())
+        .await
+        .unwrap();
+    let server_address = listener.local_addr().unwrap();
+    let mut client = AsyncEchoClient::new(server_address);
+
+    // Send without connect
+    assert!(
+        matches!(client.send("hello").await, Err(EchoError::NotRunning)),
+        "Send without connect failed - expected NotRunning"
+    );
+
+    // Clean connect
+    assert!(client.connect().await.is_ok(), "Clean connect failed");
+    let (mut server_stream, _) = listener.accept().await.unwrap();
+    assert!(
+        client.is_connected(),
+        "Clean connect failed - not connected"
+    );
+    assert!(
+        matches!(client.connect().await, Err(EchoError::AlreadyRunning)),
+        "Double connect failed - expected AlreadyRunning"
+    );
+
+    // Send
+    let frame = Framing::default().encode(b"hello").unwrap();
+    client.send("hello").await.unwrap();
+    let mut buf = vec![0; frame.len()];
+    timeout(READ_TIMEOUT, server_stream.read_exact(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(buf, frame, "Send failed - wrong message");
+
+    // Receive, including a message split across writes
+    let mut messages = client.messages();
+    server_stream.write_all(&frame).await.unwrap();
+    let binary = Framing::default().encode(&[0xff, 0x00, 0xfe]).unwrap();
+    server_stream.write_all(&binary[..2]).await.unwrap();
+    server_stream.write_all(&binary[2..]).await.unwrap();
+    let message = timeout(READ_TIMEOUT, messages.recv()).await.unwrap();
+    assert_eq!(message.unwrap().text(), Some("hello"), "Receive failed");
+    let message = timeout(READ_TIMEOUT, messages.recv()).await.unwrap();
+    assert_eq!(
+        message.unwrap().bytes(),
+        &[0xff, 0x00, 0xfe],
+        "Receive binary failed"
+    );
+    assert_stream(&messages);
+
+    // Graceful shutdown
+    assert!(client.shutdown().await.is_ok(), "Shutdown failed");
+    assert!(!client.is_connected(), "Shutdown failed - still connected");
+    let read = timeout(READ_TIMEOUT, server_stream.read(&mut buf)).await;
+    assert!(
+        matches!(read, Ok(Ok(0))),
+        "Shutdown failed - server did not see the end of the stream"
+    );
+    let message = timeout(READ_TIMEOUT, messages.recv()).await.unwrap();
+    assert!(
+        message.is_none(),
+        "Shutdown failed - message stream not ended"
+    );
+    assert!(
+        matches!(client.send("late").await, Err(EchoError::NotRunning)),
+        "Send after shutdown failed - expected NotRunning"
+    );
+
+    // Send after the server closes the connection
+    assert!(client.connect().await.is_ok(), "Reconnect failed");
+    let (server_stream, _) = listener.accept().await.unwrap();
+    let mut messages = client.messages();
+    drop(server_stream);
+    let message = timeout(READ_TIMEOUT, messages.recv()).await.unwrap();
+    assert!(
+        message.is_none(),
+        "Server disconnect failed - message stream not ended"
+    );
+    assert!(
+        matches!(client.send("late").await, Err(EchoError::NotRunning)),
+        "Send after server disconnect failed - expected NotRunning"
+    );
+    assert!(
+        client.shutdown().await.is_ok(),
+        "Shutdown after server disconnect failed"
+    );
+}
+
+fn assert_stream(_: &impl Stream) {}
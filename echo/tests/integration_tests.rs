@@ -1,16 +1,24 @@
+use std::io::{Read, Write};
 use std::net::SocketAddr;
 use std::net::TcpListener;
 use std::net::TcpStream;
+use std::net::UdpSocket;
 use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use jdn_echo::EchoClient;
+use jdn_echo::{
+    ConnectionState, EchoClient, EchoError, Framing, OverflowPolicy, Protocol, ReconnectPolicy,
+    SendOutcome,
+};
+use jdn_echo_core::protocol::{self, DELAY_MS};
 
 #[test]
 fn test_client_lifecycle() {
-    let server_address = SocketAddr::from_str("127.0.0.1:8080").unwrap();
-    let test_server = TcpListener::bind(server_address).unwrap();
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let server_address = test_server.local_addr().unwrap();
     // Control - assert client can connect
     let control_server = test_server.try_clone().unwrap();
     let control_thread = thread::spawn(move || {
@@ -22,19 +30,460 @@ fn test_client_lifecycle() {
     std::mem::drop(control_client);
 
     let mut client = EchoClient::new(server_address);
+    let state_changes = client.state_changes();
 
     // Stop without start
     client.stop();
+    assert_eq!(client.state(), ConnectionState::Idle, "Stop without start");
     assert_no_connect_attempt(&test_server, "Stop without start");
 
     // Clean start
-    client.start();
+    assert!(client.start().is_ok(), "Clean start failed");
+    assert_next_state(&state_changes, ConnectionState::Connecting, "Clean start");
+    assert_next_state(&state_changes, ConnectionState::Connected, "Clean start");
+    let server_stream = assert_connect_successful(&test_server, "Clean start");
+
+    // Reconnect after server disconnect
+    std::mem::drop(server_stream);
+    assert_next_state(
+        &state_changes,
+        ConnectionState::Reconnecting,
+        "Server disconnect",
+    );
+    assert_next_state(
+        &state_changes,
+        ConnectionState::Connected,
+        "Server disconnect",
+    );
+    assert_connect_successful(&test_server, "Server disconnect");
+
+    // Clean stop
+    client.stop();
+    assert_next_state(&state_changes, ConnectionState::Stopping, "Clean stop");
+    assert_next_state(&state_changes, ConnectionState::Stopped, "Clean stop");
+    assert!(
+        !client.is_connected(),
+        "Clean stop failed - still connected"
+    );
+
+    // Drop while running
+    client.start().unwrap();
+    assert!(client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)));
+    let (mut server_stream, _) = test_server.accept().unwrap();
+    std::mem::drop(client);
+    let mut buf = [0; 16];
+    assert_eq!(
+        server_stream.read(&mut buf).unwrap(),
+        0,
+        "Drop while running failed - client still connected"
+    );
+}
+
+#[test]
+fn test_client_messages() {
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let server_address = test_server.local_addr().unwrap();
+    let mut client = EchoClient::new(server_address);
+    let first_subscriber = client.messages();
+    let second_subscriber = client.messages();
+    client.start().unwrap();
+    assert!(client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)));
+    let (mut server_stream, _) = test_server.accept().unwrap();
+
+    let mut frames = Framing::default().encode(b"first").unwrap();
+    frames.extend(Framing::default().encode(b"second").unwrap());
+    server_stream.write_all(&frames).unwrap();
+
+    // Every subscriber receives each message, with its source
+    for subscriber in [first_subscriber, second_subscriber].iter() {
+        for expected in ["first", "second"].iter() {
+            let message = subscriber.recv_timeout(Duration::from_secs(1)).unwrap();
+            assert_eq!(message.text(), Some(*expected));
+            assert_eq!(message.source(), server_address);
+        }
+    }
+
+    // Binary messages are delivered unchanged, without a text view
+    let binary_subscriber = client.messages();
+    let binary = [0, 159, 146, 150, 255];
+    server_stream
+        .write_all(&Framing::default().encode(&binary).unwrap())
+        .unwrap();
+    let message = binary_subscriber
+        .recv_timeout(Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(message.bytes(), &binary[..]);
+    assert_eq!(message.text(), None);
+
+    // Binary messages are sent unchanged
+    client.send_bytes(&binary).unwrap();
+    let mut buf = [0; 9];
+    server_stream.read_exact(&mut buf).unwrap();
+    assert_eq!(buf.to_vec(), Framing::default().encode(&binary).unwrap());
+
+    // Round trips are not delayed by polling
+    let round_trips = 20;
+    let start = Instant::now();
+    for _ in 0..round_trips {
+        client.send_message("ping").unwrap();
+        let mut buf = [0; 8];
+        server_stream.read_exact(&mut buf).unwrap();
+        server_stream.write_all(&buf).unwrap();
+        binary_subscriber
+            .recv_timeout(Duration::from_secs(1))
+            .unwrap();
+    }
+    assert!(
+        start.elapsed() < Duration::from_millis(DELAY_MS * round_trips / 4),
+        "Round trips took {:?}",
+        start.elapsed()
+    );
+    client.stop();
+}
+
+#[test]
+fn test_client_request() {
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let server_address = test_server.local_addr().unwrap();
+    let mut client = EchoClient::new(server_address);
+    let subscriber = client.messages();
+    client.start().unwrap();
+    let (server_stream, _) = test_server.accept().unwrap();
+    let mut echo_stream = server_stream.try_clone().unwrap();
+    let (echo_sender, echo_receiver) = mpsc::channel::<bool>();
+    // Echo every frame until told to stop
+    let echo_thread = thread::spawn(move || {
+        let mut decoder = Framing::default().decoder();
+        let mut buf = [0; 256];
+        while echo_receiver.recv().unwrap() {
+            let len = echo_stream.read(&mut buf).unwrap();
+            for message in decoder.decode(&buf[..len]).unwrap() {
+                echo_stream
+                    .write_all(&Framing::default().encode(&message).unwrap())
+                    .unwrap();
+            }
+        }
+    });
+
+    // The reply is returned to the requester, not to subscribers
+    echo_sender.send(true).unwrap();
+    let response = client.request(b"ping", Duration::from_secs(1)).unwrap();
+    assert_eq!(response.message().text(), Some("ping"));
+    assert_eq!(response.message().source(), server_address);
+    assert!(response.round_trip() < Duration::from_secs(1));
+    assert!(
+        subscriber.try_recv().is_err(),
+        "Reply delivered to subscriber"
+    );
+
+    // Fire-and-forget messages are still delivered to subscribers
+    echo_sender.send(true).unwrap();
+    client.send_message("hello").unwrap();
+    let message = subscriber.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message.text(), Some("hello"));
+
+    // A request without a reply times out, and a late reply goes to subscribers
+    let result = client.request(b"late", Duration::from_millis(DELAY_MS));
+    assert!(matches!(result, Err(EchoError::TimedOut)));
+    echo_sender.send(true).unwrap();
+    let message = subscriber.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message.text(), Some("late"));
+
+    // A pending request is awaited without borrowing the client, and a dropped one leaves its reply to subscribers
+    let pending = client.send_request(b"pending").unwrap();
+    let waiter = thread::spawn(move || pending.wait(Duration::from_secs(1)));
+    client.set_token(None);
+    echo_sender.send(true).unwrap();
+    let response = waiter.join().unwrap().unwrap();
+    assert_eq!(response.message().text(), Some("pending"));
+    drop(client.send_request(b"dropped").unwrap());
+    echo_sender.send(true).unwrap();
+    let message = subscriber.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message.text(), Some("dropped"));
+
+    echo_sender.send(false).unwrap();
+    echo_thread.join().unwrap();
+    client.stop();
+}
+
+#[test]
+fn test_client_reconnect_policy() {
+    // Reserve a port on which nothing is listening
+    let server_address = TcpListener::bind(ephemeral_address())
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let mut client = EchoClient::new(server_address);
+    let (give_up_sender, give_up_receiver) = mpsc::channel();
+    let give_up_sender = Mutex::new(give_up_sender);
+    client.set_reconnect_policy(
+        ReconnectPolicy::exponential(Duration::from_millis(10), Duration::from_millis(40))
+            .with_jitter(0.5)
+            .with_max_attempts(3)
+            .on_give_up(move |attempts, _| {
+                let _ = give_up_sender.lock().unwrap().send(attempts);
+            }),
+    );
+
+    // Give up after the maximum number of attempts
+    client.start().unwrap();
+    assert_eq!(
+        give_up_receiver.recv_timeout(Duration::from_secs(1)),
+        Ok(3),
+        "Give up failed"
+    );
+    assert!(
+        client.wait_for_state(ConnectionState::Stopped, Duration::from_secs(1)),
+        "Give up failed - not stopped"
+    );
+    assert!(!client.is_running(), "Give up failed - still running");
+    assert!(
+        matches!(client.take_error(), Some(EchoError::ConnectFailed(_))),
+        "Give up failed - expected ConnectFailed"
+    );
+
+    // Start again after giving up
+    let test_server = TcpListener::bind(server_address).unwrap();
+    client.set_reconnect_policy(ReconnectPolicy::fixed(Duration::from_millis(10)));
+    assert!(client.start().is_ok(), "Start after give up failed");
+    assert!(
+        client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)),
+        "Start after give up failed - not connected"
+    );
+    assert_connect_successful(&test_server, "Start after give up");
+    client.stop();
+}
+
+#[test]
+fn test_client_outbox() {
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let server_address = test_server.local_addr().unwrap();
+    let mut client = EchoClient::new(server_address);
+    client.set_outbox_capacity(2);
+
+    // Queue while stopped, applying each overflow policy
+    client.set_overflow_policy(OverflowPolicy::Error);
+    assert_eq!(client.send_message("first").ok(), Some(SendOutcome::Queued));
+    assert_eq!(
+        client.send_message("second").ok(),
+        Some(SendOutcome::Queued)
+    );
+    assert!(
+        matches!(client.send_message("error"), Err(EchoError::OutboxFull)),
+        "Error policy failed - expected OutboxFull"
+    );
+    client.set_overflow_policy(OverflowPolicy::Block);
+    assert!(
+        matches!(client.send_message("block"), Err(EchoError::NotRunning)),
+        "Block policy failed - expected NotRunning"
+    );
+    client.set_overflow_policy(OverflowPolicy::DropNewest);
+    assert_eq!(
+        client.send_message("newest").ok(),
+        Some(SendOutcome::DroppedNewest)
+    );
+    client.set_overflow_policy(OverflowPolicy::DropOldest);
+    assert_eq!(
+        client.send_message("third").ok(),
+        Some(SendOutcome::DroppedOldest)
+    );
+    assert_eq!(client.queued_messages(), 2);
+
+    // Flush in order once connected
+    client.start().unwrap();
+    let mut server_stream = assert_connect_successful(&test_server, "Flush on connect");
+    assert_received(&mut server_stream, &["second", "third"]);
+    assert_eq!(client.queued_messages(), 0);
+
+    // Keep messages sent while disconnected, and flush them on reconnect
+    client.stop();
+    assert_eq!(
+        client.send_message("fourth").ok(),
+        Some(SendOutcome::Queued)
+    );
+    client.start().unwrap();
+    let mut server_stream = assert_connect_successful(&test_server, "Flush on reconnect");
+    assert_received(&mut server_stream, &["fourth"]);
+    client.stop();
+}
+
+#[test]
+fn test_client_udp() {
+    let test_server = UdpSocket::bind(ephemeral_address()).unwrap();
+    test_server
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let server_address = test_server.local_addr().unwrap();
+    let mut client = EchoClient::new(server_address);
+    client.set_protocol(Protocol::Udp);
+    let messages = client.messages();
+    client.start().unwrap();
+    assert!(client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)));
+
+    // Each message is sent as one datagram, without framing
+    client.send_message("ping").unwrap();
+    let mut buf = [0; 64];
+    let (len, client_address) = test_server.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"ping");
+
+    // Each datagram received is delivered as one message
+    test_server.send_to(b"pong", client_address).unwrap();
+    let message = messages.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message.text(), Some("pong"));
+    assert_eq!(message.source(), server_address);
+    client.stop();
+}
+
+#[test]
+fn test_client_token() {
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let server_address = test_server.local_addr().unwrap();
+    let mut client = EchoClient::new(server_address);
+    client.set_token(Some(String::from("secret")));
+
+    // The token is the first message of the connection
+    client.start().unwrap();
+    let mut server_stream = assert_connect_successful(&test_server, "Token");
+    client.send_message("hello").unwrap();
+    let auth_request = String::from_utf8(protocol::auth_request("secret")).unwrap();
+    assert_received(&mut server_stream, &[&auth_request, "hello"]);
+
+    // The client is only connected once the server accepts the token
+    assert_eq!(
+        client.state(),
+        ConnectionState::Connecting,
+        "Token failed - connected before accepted"
+    );
+    server_stream
+        .write_all(&Framing::default().encode(protocol::ACCEPTED).unwrap())
+        .unwrap();
+    assert!(
+        client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)),
+        "Token failed - not connected once accepted"
+    );
+
+    // A denied token stops the client with the server's reason, rather than reconnecting
+    std::mem::drop(server_stream);
+    let mut server_stream = assert_connect_successful(&test_server, "Denied token");
+    // The token is read first, since closing a socket with unread data resets the connection, discarding the denial.
+    assert_received(&mut server_stream, &[&auth_request]);
+    server_stream
+        .write_all(
+            &Framing::default()
+                .encode(&protocol::denial("token not accepted"))
+                .unwrap(),
+        )
+        .unwrap();
+    std::mem::drop(server_stream);
+    assert!(
+        client.wait_for_state(ConnectionState::Stopped, Duration::from_secs(1)),
+        "Denied token failed - not stopped"
+    );
+    assert!(
+        matches!(
+            client.take_error(),
+            Some(EchoError::AuthenticationFailed(reason)) if reason == "token not accepted"
+        ),
+        "Denied token failed - expected AuthenticationFailed"
+    );
     sleep_async_duration();
-    assert_connect_successful(&test_server, "Clean start");
+    assert_no_connect_attempt(&test_server, "Denied token");
+
+    // Without a token, the first message is the client's own
+    client.set_token(None);
+    client.start().unwrap();
+    assert!(client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)));
+    let mut server_stream = assert_connect_successful(&test_server, "No token");
+    client.send_message("plain").unwrap();
+    assert_received(&mut server_stream, &["plain"]);
+    client.stop();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_client_unix_socket() {
+    use std::os::unix::net::UnixListener;
+    use std::path::PathBuf;
+
+    let socket_path =
+        std::env::temp_dir().join(format!("jdn-echo-client-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&socket_path);
+    let test_server = UnixListener::bind(&socket_path).unwrap();
+    let mut client = EchoClient::new(PathBuf::from(&socket_path));
+    // A Unix domain socket is reached as a stream, whatever the protocol
+    client.set_protocol(Protocol::Udp);
+    client.set_token(Some(String::from("secret")));
+    let messages = client.messages();
+
+    client.start().unwrap();
+    let (mut server_stream, _) = test_server.accept().unwrap();
+    server_stream
+        .write_all(&Framing::default().encode(protocol::ACCEPTED).unwrap())
+        .unwrap();
+    assert!(client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)));
+    client.send_message("hello").unwrap();
+    let mut expected = Framing::default()
+        .encode(&protocol::auth_request("secret"))
+        .unwrap();
+    expected.extend(Framing::default().encode(b"hello").unwrap());
+    let mut buf = vec![0; expected.len()];
+    server_stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    server_stream.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, expected);
+
+    server_stream
+        .write_all(&Framing::default().encode(b"reply").unwrap())
+        .unwrap();
+    let message = messages.recv_timeout(Duration::from_secs(1)).unwrap();
+    assert_eq!(message.text_lossy(), "reply");
+    client.stop();
+    std::mem::drop(test_server);
+    let _ = std::fs::remove_file(&socket_path);
+}
+
+fn ephemeral_address() -> SocketAddr {
+    SocketAddr::from_str("127.0.0.1:0").unwrap()
+}
+
+fn assert_next_state(
+    state_changes: &mpsc::Receiver<ConnectionState>,
+    expected: ConnectionState,
+    test_case: &'static str,
+) {
+    let state = state_changes.recv_timeout(Duration::from_secs(1));
+    assert_eq!(state, Ok(expected), "{} failed", test_case);
+}
+
+fn assert_connect_successful(test_server: &TcpListener, test_case: &'static str) -> TcpStream {
+    let result = test_server.accept();
+    assert!(result.is_ok(), "{} failed", test_case);
+    result.unwrap().0
 }
 
-fn assert_connect_successful(test_server: &TcpListener, test_case: &'static str) {
-    assert!(test_server.accept().is_ok(), "{} failed", test_case);
+fn assert_received(server_stream: &mut TcpStream, expected: &[&str]) {
+    server_stream
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let mut decoder = Framing::default().decoder();
+    let mut received = Vec::new();
+    let mut buf = [0; 64];
+    while received.len() < expected.len() {
+        let len = server_stream.read(&mut buf).unwrap();
+        assert!(
+            len > 0,
+            "Server stream closed before all messages were received"
+        );
+        received.extend(
+            decoder
+                .decode(&buf[..len])
+                .unwrap()
+                .into_iter()
+                .map(|message| String::from_utf8(message).unwrap()),
+        );
+    }
+    assert_eq!(received, expected);
 }
 
 fn assert_no_connect_attempt(test_server: &TcpListener, test_case: &'static str) {
@@ -0,0 +1,96 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use jdn_echo::{ConnectionState, EchoClient, EchoError};
+use jdn_echo_core::memory;
+use jdn_echo_core::protocol;
+use jdn_echo_core::transport::Connector;
+use jdn_echo_server::{ConsoleHandler, EchoServer};
+
+#[test]
+fn test_client_memory_transport() {
+    // Neither the client nor the server uses the address, so no port is bound
+    let unused_address = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let (connector, acceptor) = memory::channel();
+    let connector: Arc<dyn Connector> = Arc::new(connector);
+    let mut server = EchoServer::new(unused_address, Arc::new(ConsoleHandler));
+    server.set_acceptor(Some(Arc::new(acceptor)));
+    server.set_tokens(vec!["secret"]);
+    server.start().unwrap();
+
+    // Requests are echoed by the server
+    let mut client = EchoClient::new(unused_address);
+    client.set_connector(Some(Arc::clone(&connector)));
+    client.set_token(Some(String::from("secret")));
+    client.start().unwrap();
+    assert!(client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)));
+    let response = client.request(b"ping", Duration::from_secs(1)).unwrap();
+    assert_eq!(response.message().bytes(), b"ping");
+
+    // A client with the wrong token is denied
+    let mut intruder = EchoClient::new(unused_address);
+    intruder.set_connector(Some(connector));
+    intruder.set_token(Some(String::from("guess")));
+    intruder.start().unwrap();
+    assert!(
+        intruder.wait_for_state(ConnectionState::Stopped, Duration::from_secs(1)),
+        "Wrong token failed - not stopped"
+    );
+    assert!(
+        matches!(
+            intruder.take_error(),
+            Some(EchoError::AuthenticationFailed(_))
+        ),
+        "Wrong token failed - expected AuthenticationFailed"
+    );
+
+    client.stop();
+    server.stop();
+}
+
+#[test]
+fn test_client_spoofed_denial() {
+    let unused_address = SocketAddr::from_str("127.0.0.1:0").unwrap();
+    let (connector, acceptor) = memory::channel();
+    let connector: Arc<dyn Connector> = Arc::new(connector);
+    let mut server = EchoServer::new(unused_address, Arc::new(ConsoleHandler));
+    server.set_acceptor(Some(Arc::new(acceptor)));
+    server.set_tokens(vec!["secret"]);
+    server.start().unwrap();
+
+    let mut clients: Vec<EchoClient> = (0..2)
+        .map(|_| {
+            let mut client = EchoClient::new(unused_address);
+            client.set_connector(Some(Arc::clone(&connector)));
+            client.set_token(Some(String::from("secret")));
+            client.start().unwrap();
+            assert!(client.wait_for_state(ConnectionState::Connected, Duration::from_secs(1)));
+            client
+        })
+        .collect();
+    let own_messages = clients[0].messages();
+    let messages = clients[1].messages();
+
+    // A message that looks like a denial is broadcast like any other, rather than stopping its recipients
+    let spoofed = String::from_utf8(protocol::denial("spoofed")).unwrap();
+    clients[0].send_message(&spoofed).unwrap();
+    for messages in [&messages, &own_messages] {
+        let message = messages.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(message.text_lossy(), spoofed);
+    }
+    for client in &mut clients {
+        assert_eq!(
+            client.state(),
+            ConnectionState::Connected,
+            "Spoofed denial failed - client disconnected"
+        );
+        assert!(
+            client.take_error().is_none(),
+            "Spoofed denial failed - error reported"
+        );
+        client.stop();
+    }
+    server.stop();
+}
@@ -0,0 +1,454 @@
+#![cfg(feature = "tls")]
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, DnType, IsCa, KeyPair};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+use jdn_echo::{ConnectionState, EchoClient, EchoError, Framing, Protocol, ReconnectPolicy};
+use jdn_echo_core::memory::{self, MemoryAcceptor};
+use jdn_echo_core::transport::Acceptor;
+
+#[test]
+fn test_client_tls() {
+    let dir = std::env::temp_dir().join(format!("jdn-echo-client-tls-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = CertificateParams::new(vec!["127.0.0.1".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca, &ca_key)
+        .unwrap();
+    let other = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+    let ca_path = write_pem(&dir, "ca.pem", &ca.pem());
+    let server_path = write_pem(&dir, "server.pem", &server_cert.pem());
+    let other_path = write_pem(&dir, "other.pem", &other.cert.pem());
+    let server_config = server_config(
+        CertifiedKey {
+            cert: server_cert,
+            key_pair: server_key,
+        },
+        None,
+    );
+
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let server_address = test_server.local_addr().unwrap();
+    let mut client = EchoClient::new(server_address);
+    client.set_reconnect_policy(
+        ReconnectPolicy::fixed(Duration::from_millis(10)).with_max_attempts(1),
+    );
+
+    // Invalid certificate files
+    assert!(
+        matches!(
+            client.set_tls_ca(&dir.join("missing.pem")),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Missing CA failed - expected TlsConfigFailed"
+    );
+    assert!(
+        matches!(
+            client.set_tls_pinned_certificate(&dir.join("missing.pem")),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Missing pinned certificate failed - expected TlsConfigFailed"
+    );
+
+    // Server certificate issued by the trusted CA
+    assert!(client.set_tls_ca(&ca_path).is_ok(), "Valid CA failed");
+    let server_thread = spawn_tls_echo(&test_server, &server_config);
+    assert_echoed(&mut client, "Trusted CA");
+    assert!(
+        server_thread.join().unwrap().is_ok(),
+        "Trusted CA failed - server error"
+    );
+
+    // Server certificate pinned
+    assert!(
+        client.set_tls_pinned_certificate(&server_path).is_ok(),
+        "Valid pinned certificate failed"
+    );
+    let server_thread = spawn_tls_echo(&test_server, &server_config);
+    assert_echoed(&mut client, "Pinned certificate");
+    assert!(
+        server_thread.join().unwrap().is_ok(),
+        "Pinned certificate failed - server error"
+    );
+
+    // Server certificate not issued by the trusted CA
+    assert!(client.set_tls_ca(&other_path).is_ok());
+    let server_thread = spawn_tls_echo(&test_server, &server_config);
+    assert_rejected(&mut client, "Untrusted CA");
+    assert!(
+        server_thread.join().unwrap().is_err(),
+        "Untrusted CA failed - handshake completed"
+    );
+
+    // Server certificate differs from the pinned certificate
+    assert!(client.set_tls_pinned_certificate(&other_path).is_ok());
+    let server_thread = spawn_tls_echo(&test_server, &server_config);
+    assert_rejected(&mut client, "Wrong pinned certificate");
+    assert!(
+        server_thread.join().unwrap().is_err(),
+        "Wrong pinned certificate failed - handshake completed"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_client_tls_client_certificate() {
+    let dir = std::env::temp_dir().join(format!("jdn-echo-client-mtls-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = CertificateParams::new(vec!["127.0.0.1".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca, &ca_key)
+        .unwrap();
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(Vec::new()).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "echo-client");
+    let client_cert = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+    let ca_path = write_pem(&dir, "ca.pem", &ca.pem());
+    let client_cert_path = write_pem(&dir, "client.pem", &client_cert.pem());
+    let client_key_path = write_pem(&dir, "client.key", &client_key.serialize_pem());
+    let server_config = server_config(
+        CertifiedKey {
+            cert: server_cert,
+            key_pair: server_key,
+        },
+        Some(ca.der()),
+    );
+
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let mut client = EchoClient::new(test_server.local_addr().unwrap());
+
+    // Invalid certificate files
+    assert!(
+        matches!(
+            client.set_tls_client_certificate(&dir.join("missing.pem"), &client_key_path),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Missing client certificate failed - expected TlsConfigFailed"
+    );
+    assert!(
+        matches!(
+            client.set_tls_client_certificate(&client_cert_path, &dir.join("missing.key")),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Missing client key failed - expected TlsConfigFailed"
+    );
+
+    // The client certificate may be set before the server verification
+    assert!(
+        client
+            .set_tls_client_certificate(&client_cert_path, &client_key_path)
+            .is_ok(),
+        "Valid client certificate failed"
+    );
+    assert!(client.set_tls_ca(&ca_path).is_ok(), "Valid CA failed");
+    let server_thread = spawn_tls_echo(&test_server, &server_config);
+    assert_echoed(&mut client, "Client certificate");
+    assert!(
+        server_thread.join().unwrap().is_ok(),
+        "Client certificate failed - server error"
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_client_tls_simultaneous_writes() {
+    let dir = std::env::temp_dir().join(format!("jdn-echo-client-tls-io-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let server = rcgen::generate_simple_self_signed(vec!["127.0.0.1".to_string()]).unwrap();
+    let server_path = write_pem(&dir, "server.pem", &server.cert.pem());
+    let server_config = server_config(server, None);
+
+    let test_server = TcpListener::bind(ephemeral_address()).unwrap();
+    let mut client = EchoClient::new(test_server.local_addr().unwrap());
+    client.set_tls_pinned_certificate(&server_path).unwrap();
+    let subscriber = client.messages();
+
+    // Both ends send far more than the socket buffers can hold before either reads, so the client must keep
+    // reading while its writes are blocked
+    let message = vec![b'x'; 64 * 1024];
+    let frame = Framing::default().encode(&message).unwrap();
+    let count = 256;
+    let test_server = test_server.try_clone().unwrap();
+    let server_frame = frame.clone();
+    let server_thread = thread::spawn(move || -> io::Result<()> {
+        let (socket, _) = test_server.accept()?;
+        socket.set_read_timeout(Some(Duration::from_secs(10)))?;
+        let session = ServerConnection::new(server_config).unwrap();
+        let mut stream = StreamOwned::new(session, socket);
+        for _ in 0..count {
+            stream.write_all(&server_frame)?;
+        }
+        stream.flush()?;
+        let mut received = vec![0; server_frame.len()];
+        for _ in 0..count {
+            stream.read_exact(&mut received)?;
+        }
+        Ok(())
+    });
+    client.start().unwrap();
+    assert!(
+        client.wait_for_state(ConnectionState::Connected, Duration::from_secs(2)),
+        "Simultaneous writes failed - not connected"
+    );
+    for _ in 0..count {
+        client.send_bytes(&message).unwrap();
+    }
+    for _ in 0..count {
+        let received = subscriber.recv_timeout(Duration::from_secs(10));
+        assert!(
+            matches!(&received, Ok(received) if received.bytes() == message),
+            "Simultaneous writes failed - message not received"
+        );
+    }
+    assert!(
+        server_thread.join().unwrap().is_ok(),
+        "Simultaneous writes failed - server error"
+    );
+    client.stop();
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_client_tls_streams() {
+    let dir = std::env::temp_dir().join(format!(
+        "jdn-echo-client-tls-streams-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key).unwrap();
+    let server_key = KeyPair::generate().unwrap();
+    let server_cert = CertificateParams::new(vec!["localhost".to_string()])
+        .unwrap()
+        .signed_by(&server_key, &ca, &ca_key)
+        .unwrap();
+    let ca_path = write_pem(&dir, "ca.pem", &ca.pem());
+    let server_path = write_pem(&dir, "server.pem", &server_cert.pem());
+    let server_config = server_config(
+        CertifiedKey {
+            cert: server_cert,
+            key_pair: server_key,
+        },
+        None,
+    );
+
+    let mut client = EchoClient::new(ephemeral_address());
+    client.set_reconnect_policy(
+        ReconnectPolicy::fixed(Duration::from_millis(10)).with_max_attempts(1),
+    );
+    client.set_tls_ca(&ca_path).unwrap();
+
+    // TLS is not supported over UDP
+    client.set_protocol(Protocol::Udp);
+    assert!(
+        matches!(client.start(), Err(EchoError::TlsConfigFailed(_))),
+        "UDP failed - expected TlsConfigFailed"
+    );
+    assert!(!client.is_running(), "UDP failed - client running");
+    client.set_protocol(Protocol::Tcp);
+
+    // A server reached through a connector has no IP address for its certificate to be valid for
+    let (connector, acceptor) = memory::channel();
+    let acceptor = Arc::new(acceptor);
+    client.set_connector(Some(Arc::new(connector)));
+    assert!(
+        matches!(client.start(), Err(EchoError::TlsConfigFailed(_))),
+        "Connector without server name failed - expected TlsConfigFailed"
+    );
+    assert!(
+        matches!(
+            client.set_tls_server_name(Some("not a name")),
+            Err(EchoError::TlsConfigFailed(_))
+        ),
+        "Invalid server name failed - expected TlsConfigFailed"
+    );
+
+    // Server certificate issued by the trusted CA for the given name
+    client.set_tls_server_name(Some("localhost")).unwrap();
+    let server_thread = spawn_memory_tls_echo(&acceptor, &server_config);
+    assert_echoed(&mut client, "Connector with server name");
+    assert!(
+        server_thread.join().unwrap().is_ok(),
+        "Connector with server name failed - server error"
+    );
+
+    // A pinned certificate needs no server name
+    client.set_tls_server_name(None).unwrap();
+    client.set_tls_pinned_certificate(&server_path).unwrap();
+    let server_thread = spawn_memory_tls_echo(&acceptor, &server_config);
+    assert_echoed(&mut client, "Connector with pinned certificate");
+    assert!(
+        server_thread.join().unwrap().is_ok(),
+        "Connector with pinned certificate failed - server error"
+    );
+    client.set_connector(None);
+
+    // TLS is used over Unix domain sockets
+    #[cfg(unix)]
+    {
+        let socket_path = dir.join("server.sock");
+        let test_server = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        client.set_address(jdn_echo::Endpoint::Unix(socket_path));
+        let config = Arc::clone(&server_config);
+        let server_thread = thread::spawn(move || {
+            let (socket, _) = test_server.accept()?;
+            socket.set_read_timeout(Some(Duration::from_secs(2)))?;
+            echo_tls(socket, config)
+        });
+        assert_echoed(&mut client, "Unix domain socket");
+        assert!(
+            server_thread.join().unwrap().is_ok(),
+            "Unix domain socket failed - server error"
+        );
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn ephemeral_address() -> SocketAddr {
+    SocketAddr::from_str("127.0.0.1:0").unwrap()
+}
+
+fn write_pem(dir: &std::path::Path, name: &str, pem: &str) -> PathBuf {
+    let path = dir.join(name);
+    fs::write(&path, pem).unwrap();
+    path
+}
+
+/// Builds the configuration of a test server, which requires a client certificate issued by the given CA, if any.
+fn server_config(
+    certified_key: CertifiedKey,
+    client_ca: Option<&CertificateDer<'static>>,
+) -> Arc<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let key =
+        PrivateKeyDer::from_pem_slice(certified_key.key_pair.serialize_pem().as_bytes()).unwrap();
+    let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let builder = match client_ca {
+        Some(ca) => {
+            let mut roots = RootCertStore::empty();
+            roots.add(ca.clone()).unwrap();
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let config = builder
+        .with_single_cert(vec![certified_key.cert.der().clone()], key)
+        .unwrap();
+    Arc::new(config)
+}
+
+/// Accepts one TLS client, echoes its first message, then waits for it to disconnect.
+fn spawn_tls_echo(
+    test_server: &TcpListener,
+    config: &Arc<ServerConfig>,
+) -> thread::JoinHandle<io::Result<()>> {
+    let test_server = test_server.try_clone().unwrap();
+    let config = Arc::clone(config);
+    thread::spawn(move || {
+        let (socket, _) = test_server.accept()?;
+        socket.set_read_timeout(Some(Duration::from_secs(2)))?;
+        echo_tls(socket, config)
+    })
+}
+
+/// Accepts one TLS client of an in-memory transport, echoes its first message, then waits for it to disconnect.
+fn spawn_memory_tls_echo(
+    acceptor: &Arc<MemoryAcceptor>,
+    config: &Arc<ServerConfig>,
+) -> thread::JoinHandle<io::Result<()>> {
+    let acceptor = Arc::clone(acceptor);
+    let config = Arc::clone(config);
+    thread::spawn(move || {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let socket = loop {
+            match acceptor.accept() {
+                Ok((socket, _)) => break socket,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock && Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(1))
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        echo_tls(socket, config)
+    })
+}
+
+fn echo_tls(socket: impl Read + Write, config: Arc<ServerConfig>) -> io::Result<()> {
+    let session = ServerConnection::new(config).unwrap();
+    let mut stream = StreamOwned::new(session, socket);
+    let mut frame = vec![0; Framing::default().encode(b"hello").unwrap().len()];
+    stream.read_exact(&mut frame)?;
+    stream.write_all(&frame)?;
+    stream.flush()?;
+    let _ = stream.read(&mut frame);
+    Ok(())
+}
+
+fn assert_echoed(client: &mut EchoClient, test_case: &'static str) {
+    let subscriber = client.messages();
+    assert!(client.start().is_ok(), "{} failed - start", test_case);
+    assert!(
+        client.wait_for_state(ConnectionState::Connected, Duration::from_secs(2)),
+        "{} failed - not connected",
+        test_case
+    );
+    client.send_message("hello").unwrap();
+    let message = subscriber.recv_timeout(Duration::from_secs(2));
+    assert!(message.is_ok(), "{} failed - no echo", test_case);
+    assert_eq!(
+        message.unwrap().text(),
+        Some("hello"),
+        "{} failed",
+        test_case
+    );
+    client.stop();
+}
+
+fn assert_rejected(client: &mut EchoClient, test_case: &'static str) {
+    assert!(client.start().is_ok(), "{} failed - start", test_case);
+    assert!(
+        client.wait_for_state(ConnectionState::Stopped, Duration::from_secs(2)),
+        "{} failed - not stopped",
+        test_case
+    );
+    assert!(
+        matches!(client.take_error(), Some(EchoError::ConnectFailed(_))),
+        "{} failed - expected ConnectFailed",
+        test_case
+    );
+}